@@ -125,7 +125,7 @@ impl Address for Ipv4Addr {
     }
 
     fn mask(self, masklen: u32) -> Self {
-        debug_assert!(masklen <= 32);
+        assert!(masklen <= 32, "masklen {} exceeds 32 bits", masklen);
         let ip = u32::from(self);
         let masked = match masklen {
             0 => 0,
@@ -173,7 +173,7 @@ impl Address for Ipv6Addr {
     }
 
     fn mask(self, masklen: u32) -> Self {
-        debug_assert!(masklen <= 128);
+        assert!(masklen <= 128, "masklen {} exceeds 128 bits", masklen);
         let mut ret = self.segments();
         for i in ((masklen + 15) / 16)..8 {
             ret[i as usize] = 0;
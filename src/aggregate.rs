@@ -0,0 +1,410 @@
+// Copyright 2016 Hroi Sigurdsson
+//
+// Licensed under the MIT license <LICENSE-MIT or http://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed except according to those terms.
+
+use std::cmp;
+
+use address::Address;
+use tree_bitmap::{NodeData, TrieCursor};
+use {check_masklen, IpLookupTable};
+
+/// Monoid used by ```AggregateTable``` to summarize values. ```combine``` must
+/// be associative and commutative, with ```empty``` as its identity.
+pub trait Aggregate<T> {
+    /// Type of the aggregate.
+    type Output: Clone;
+
+    /// Returns the aggregate of no values.
+    fn empty(&self) -> Self::Output;
+
+    /// Returns the aggregate of a single value.
+    fn single(&self, value: &T) -> Self::Output;
+
+    /// Combines two aggregates.
+    fn combine(&self, a: &Self::Output, b: &Self::Output) -> Self::Output;
+}
+
+/// Aggregate counting the number of prefixes.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Count;
+
+impl<T> Aggregate<T> for Count {
+    type Output = usize;
+
+    fn empty(&self) -> usize {
+        0
+    }
+
+    fn single(&self, _value: &T) -> usize {
+        1
+    }
+
+    fn combine(&self, a: &usize, b: &usize) -> usize {
+        a + b
+    }
+}
+
+/// IP lookup table caching an aggregate of the values stored under each node
+/// of the trie, for summarizing all prefixes under a prefix without visiting
+/// them.
+///
+/// Each trie node has a slot in an allocator laid out like the trie nodes,
+/// holding the index of the node's aggregate. This costs a ```u32``` and an
+/// aggregate per trie node on top of the table itself. Insert, remove and
+/// ```remove_subtree``` recompute the aggregates of the nodes on the path to
+/// the prefix, each from the values of the node and the cached aggregates of
+/// its up to 16 children. ```retain``` recomputes all aggregates.
+/// ```aggregate_under``` descends to the node holding the prefix and combines
+/// the aggregates of at most 16 children with the matching values of that
+/// node.
+///
+/// # Example
+///
+/// ```
+/// use treebitmap::{Aggregate, AggregateTable, Count};
+/// use std::net::Ipv4Addr;
+///
+/// struct Sum;
+///
+/// impl Aggregate<u64> for Sum {
+///     type Output = u64;
+///
+///     fn empty(&self) -> u64 {
+///         0
+///     }
+///
+///     fn single(&self, value: &u64) -> u64 {
+///         *value
+///     }
+///
+///     fn combine(&self, a: &u64, b: &u64) -> u64 {
+///         a + b
+///     }
+/// }
+///
+/// let mut table = AggregateTable::new(Sum);
+/// table.insert(Ipv4Addr::new(10, 0, 0, 0), 8, 100);
+/// table.insert(Ipv4Addr::new(10, 1, 0, 0), 16, 20);
+/// table.insert(Ipv4Addr::new(10, 1, 2, 0), 24, 3);
+/// table.insert(Ipv4Addr::new(192, 168, 0, 0), 16, 4000);
+///
+/// assert_eq!(table.aggregate_under(Ipv4Addr::new(10, 0, 0, 0), 8), 123);
+/// assert_eq!(table.aggregate_under(Ipv4Addr::new(10, 1, 0, 0), 16), 23);
+/// assert_eq!(table.aggregate_under(Ipv4Addr::new(0, 0, 0, 0), 0), 4123);
+///
+/// let mut table = AggregateTable::new(Count);
+/// table.insert(Ipv4Addr::new(10, 0, 0, 0), 8, ());
+/// table.insert(Ipv4Addr::new(10, 1, 0, 0), 16, ());
+/// assert_eq!(table.aggregate_under(Ipv4Addr::new(10, 0, 0, 0), 8), 2);
+/// ```
+pub struct AggregateTable<A, T, M: Aggregate<T>> {
+    table: IpLookupTable<A, T>,
+    aggregate: M,
+    nodes: NodeData<M::Output>, // aggregate of each trie node, if cached
+}
+
+impl<A, T, M> AggregateTable<A, T, M>
+where
+    A: Address,
+    M: Aggregate<T>,
+{
+    /// Initialize an empty table aggregating values with ```aggregate```.
+    pub fn new(aggregate: M) -> Self {
+        let mut table = IpLookupTable::new();
+        table.inner.log_node_ops(true);
+        AggregateTable {
+            table,
+            aggregate,
+            nodes: NodeData::new(),
+        }
+    }
+
+    /// Returns the underlying lookup table, for lookups and iteration.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use treebitmap::{AggregateTable, Count};
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let mut table = AggregateTable::new(Count);
+    /// table.insert(Ipv4Addr::new(10, 0, 0, 0), 8, "foo");
+    /// let result = table.table().longest_match(Ipv4Addr::new(10, 1, 2, 3));
+    /// assert_eq!(result, Some((Ipv4Addr::new(10, 0, 0, 0), 8, &"foo")));
+    /// ```
+    pub fn table(&self) -> &IpLookupTable<A, T> {
+        &self.table
+    }
+
+    /// Consumes the table, returning the underlying lookup table.
+    pub fn into_table(mut self) -> IpLookupTable<A, T> {
+        self.table.inner.log_node_ops(false);
+        self.table
+    }
+
+    /// Returns the number of prefixes in the table.
+    pub fn len(&self) -> usize {
+        self.table.len()
+    }
+
+    /// Returns true if the table is empty.
+    pub fn is_empty(&self) -> bool {
+        self.table.is_empty()
+    }
+
+    /// Insert a value for the prefix designated by ip and masklen, updating
+    /// the aggregates covering it. If prefix existed previously, the old value
+    /// is returned.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use treebitmap::{AggregateTable, Count};
+    /// use std::net::Ipv6Addr;
+    ///
+    /// let mut table = AggregateTable::new(Count);
+    /// let prefix = Ipv6Addr::new(0x2001, 0xdb8, 0xdead, 0xbeef, 0, 0, 0, 0);
+    /// assert_eq!(table.insert(prefix, 64, "foo"), None);
+    /// assert_eq!(table.insert(prefix, 64, "bar"), Some("foo"));
+    /// assert_eq!(table.aggregate_under(prefix, 32), 1);
+    /// ```
+    pub fn insert(&mut self, ip: A, masklen: u32, value: T) -> Option<T> {
+        self.update(ip, masklen, |table| table.insert(ip, masklen, value))
+    }
+
+    /// Remove an entry from the table, updating the aggregates covering it.
+    /// If the prefix existed previously, the value is returned.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use treebitmap::{AggregateTable, Count};
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let mut table = AggregateTable::new(Count);
+    /// table.insert(Ipv4Addr::new(10, 0, 0, 0), 8, "foo");
+    /// table.insert(Ipv4Addr::new(10, 1, 0, 0), 16, "bar");
+    /// assert_eq!(table.remove(Ipv4Addr::new(10, 1, 0, 0), 16), Some("bar"));
+    /// assert_eq!(table.aggregate_under(Ipv4Addr::new(10, 0, 0, 0), 8), 1);
+    /// ```
+    pub fn remove(&mut self, ip: A, masklen: u32) -> Option<T> {
+        self.update(ip, masklen, |table| table.remove(ip, masklen))
+    }
+
+    /// Remove the prefix designated by ip and masklen along with all more
+    /// specific prefixes contained within it, updating the aggregates covering
+    /// them. Returns the number of prefixes removed. Bits to the right of mask
+    /// are ignored.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use treebitmap::{AggregateTable, Count};
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let mut table = AggregateTable::new(Count);
+    /// table.insert(Ipv4Addr::new(10, 0, 0, 0), 8, ());
+    /// table.insert(Ipv4Addr::new(10, 1, 0, 0), 16, ());
+    /// table.insert(Ipv4Addr::new(10, 1, 2, 0), 24, ());
+    ///
+    /// assert_eq!(table.remove_subtree(Ipv4Addr::new(10, 1, 0, 0), 16), 2);
+    /// assert_eq!(table.aggregate_under(Ipv4Addr::new(10, 0, 0, 0), 8), 1);
+    /// ```
+    pub fn remove_subtree(&mut self, ip: A, masklen: u32) -> usize {
+        self.update(ip, masklen, |table| table.remove_subtree(ip, masklen))
+    }
+
+    /// Retain only the prefixes for which ```f``` returns true, recomputing
+    /// all aggregates.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use treebitmap::{AggregateTable, Count};
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let mut table = AggregateTable::new(Count);
+    /// table.insert(Ipv4Addr::new(10, 0, 0, 0), 8, ());
+    /// table.insert(Ipv4Addr::new(10, 1, 0, 0), 16, ());
+    /// table.insert(Ipv4Addr::new(10, 1, 2, 0), 24, ());
+    ///
+    /// table.retain(|_ip, masklen, _val| masklen < 24);
+    /// assert_eq!(table.aggregate_under(Ipv4Addr::new(10, 0, 0, 0), 8), 2);
+    /// ```
+    pub fn retain<F>(&mut self, f: F)
+    where
+        F: FnMut(A, u32, &mut T) -> bool,
+    {
+        let mut cursor = self.table.trie_cursor();
+        unset_all(&mut self.nodes, &mut cursor);
+        self.modify(|table| table.retain(f));
+        self.refresh();
+    }
+
+    /// Returns the aggregate of the values of the prefix designated by ip and
+    /// masklen and all prefixes more specific than it. Bits to the right of
+    /// mask are ignored.
+    ///
+    /// # Panics
+    ///
+    /// Panics if masklen exceeds the number of bits in the address.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use treebitmap::{AggregateTable, Count};
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let mut table = AggregateTable::new(Count);
+    /// table.insert(Ipv4Addr::new(10, 0, 0, 0), 8, ());
+    /// table.insert(Ipv4Addr::new(10, 1, 0, 0), 16, ());
+    /// table.insert(Ipv4Addr::new(10, 1, 2, 0), 24, ());
+    ///
+    /// assert_eq!(table.aggregate_under(Ipv4Addr::new(10, 1, 0, 0), 15), 2);
+    /// assert_eq!(table.aggregate_under(Ipv4Addr::new(10, 1, 2, 3), 23), 1);
+    /// assert_eq!(table.aggregate_under(Ipv4Addr::new(11, 0, 0, 0), 8), 0);
+    /// ```
+    pub fn aggregate_under(&self, ip: A, masklen: u32) -> M::Output {
+        check_masklen(ip, masklen);
+        let nibbles = ip.nibbles();
+        let nibbles = nibbles.as_ref();
+        let mut cursor = self.table.trie_cursor();
+        while cursor.depth() < masklen as usize / 4 && cursor.descend(nibbles[cursor.depth()]) {}
+
+        // bits of the prefix within the current node
+        let bits = masklen - cursor.depth() as u32 * 4;
+        if bits == 0 {
+            return self.node_aggregate(&mut cursor);
+        }
+        let nibble = nibbles[cursor.depth()];
+        let mut ret = self.aggregate.empty();
+        for (prefix, result_masklen, value) in cursor.results() {
+            if result_masklen >= masklen && covers(nibble, prefix, bits) {
+                ret = self.aggregate.combine(&ret, &self.aggregate.single(value));
+            }
+        }
+        if bits <= 4 {
+            for child in cursor
+                .children()
+                .filter(|&child| covers(nibble, child, bits))
+            {
+                cursor.descend(child);
+                ret = self
+                    .aggregate
+                    .combine(&ret, &self.node_aggregate(&mut cursor));
+                cursor.ascend();
+            }
+        }
+        ret
+    }
+
+    /// Returns the aggregate of the node at ```cursor```, computing it if it
+    /// is not cached, which is only the case after a change panicked.
+    fn node_aggregate(&self, cursor: &mut TrieCursor<'_, T>) -> M::Output {
+        if let Some(ret) = self.nodes.get(cursor.location()) {
+            return ret.clone();
+        }
+        let mut ret = self.aggregate.empty();
+        for (_, _, value) in cursor.results() {
+            ret = self.aggregate.combine(&ret, &self.aggregate.single(value));
+        }
+        for child in cursor.children() {
+            cursor.descend(child);
+            ret = self.aggregate.combine(&ret, &self.node_aggregate(cursor));
+            cursor.ascend();
+        }
+        ret
+    }
+
+    /// Applies ```f``` to the table, then recomputes the aggregates of the
+    /// nodes which may have changed by inserting or removing prefixes under
+    /// ```ip```/```masklen```.
+    fn update<R, F>(&mut self, ip: A, masklen: u32, f: F) -> R
+    where
+        F: FnOnce(&mut IpLookupTable<A, T>) -> R,
+    {
+        // drop the aggregates first, so they are recomputed even if f panics
+        let nibbles = ip.nibbles();
+        let nibbles = nibbles.as_ref();
+        let depth = cmp::min(masklen as usize / 4, nibbles.len());
+        let mut cursor = self.table.trie_cursor();
+        loop {
+            self.nodes.unset(cursor.location());
+            if cursor.depth() >= depth || !cursor.descend(nibbles[cursor.depth()]) {
+                break;
+            }
+        }
+        let ret = self.modify(f);
+        self.refresh();
+        ret
+    }
+
+    /// Applies ```f``` to the table, moving the cached aggregates along with
+    /// the trie nodes they belong to, also if ```f``` panics.
+    fn modify<R, F>(&mut self, f: F) -> R
+    where
+        F: FnOnce(&mut IpLookupTable<A, T>) -> R,
+    {
+        struct Sync<'a, A: 'a, T: 'a, V: 'a> {
+            table: &'a mut IpLookupTable<A, T>,
+            nodes: &'a mut NodeData<V>,
+        }
+
+        impl<'a, A: 'a, T: 'a, V: 'a> Drop for Sync<'a, A, T, V> {
+            fn drop(&mut self) {
+                self.nodes.sync(&mut self.table.inner);
+            }
+        }
+
+        let sync = Sync {
+            table: &mut self.table,
+            nodes: &mut self.nodes,
+        };
+        f(sync.table)
+    }
+
+    /// Computes and caches the missing aggregates.
+    fn refresh(&mut self) {
+        let mut cursor = self.table.trie_cursor();
+        aggregate_node(&self.aggregate, &mut self.nodes, &mut cursor);
+    }
+}
+
+/// Returns true if the first ```bits``` bits of ```a``` and ```b``` are equal.
+fn covers(a: u8, b: u8, bits: u32) -> bool {
+    (a ^ b) >> (4 - cmp::min(bits, 4)) == 0
+}
+
+/// Returns the aggregate of the node at ```cursor```, computing and caching
+/// it and any missing aggregates of its children.
+fn aggregate_node<T, M: Aggregate<T>>(
+    aggregate: &M,
+    nodes: &mut NodeData<M::Output>,
+    cursor: &mut TrieCursor<'_, T>,
+) -> M::Output {
+    if let Some(ret) = nodes.get(cursor.location()) {
+        return ret.clone();
+    }
+    let mut ret = aggregate.empty();
+    for (_, _, value) in cursor.results() {
+        ret = aggregate.combine(&ret, &aggregate.single(value));
+    }
+    for child in cursor.children() {
+        cursor.descend(child);
+        ret = aggregate.combine(&ret, &aggregate_node(aggregate, nodes, cursor));
+        cursor.ascend();
+    }
+    nodes.set(cursor.location(), ret.clone());
+    ret
+}
+
+/// Drops the cached aggregates of the node at ```cursor``` and its children.
+fn unset_all<T, V>(nodes: &mut NodeData<V>, cursor: &mut TrieCursor<'_, T>) {
+    nodes.unset(cursor.location());
+    for child in cursor.children() {
+        cursor.descend(child);
+        unset_all(nodes, cursor);
+        cursor.ascend();
+    }
+}
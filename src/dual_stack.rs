@@ -0,0 +1,616 @@
+// Copyright 2016 Hroi Sigurdsson
+//
+// Licensed under the MIT license <LICENSE-MIT or http://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed except according to those terms.
+
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use {IpLookupTable, Iter, IterMut, TableError};
+
+/// IP lookup table holding both IPv4 and IPv6 prefixes, keyed by ```IpAddr```.
+///
+/// Each address family is stored in its own ```IpLookupTable```, which can be
+/// accessed directly with ```ipv4``` and ```ipv6``` for the operations not
+/// provided here.
+///
+/// # Example
+///
+/// ```
+/// use treebitmap::DualStackTable;
+/// use std::net::IpAddr;
+///
+/// let mut table = DualStackTable::new();
+/// table.insert("10.0.0.0".parse().unwrap(), 8, "foo");
+/// table.insert("2001:db8::".parse().unwrap(), 32, "bar");
+///
+/// let ip: IpAddr = "2001:db8::1".parse().unwrap();
+/// assert_eq!(table.longest_match(ip), Some(("2001:db8::".parse().unwrap(), 32, &"bar")));
+/// assert_eq!(table.len(), 2);
+/// ```
+pub struct DualStackTable<T> {
+    ipv4: IpLookupTable<Ipv4Addr, T>,
+    ipv6: IpLookupTable<Ipv6Addr, T>,
+}
+
+impl<T> DualStackTable<T> {
+    /// Initialize an empty table.
+    pub fn new() -> Self {
+        DualStackTable {
+            ipv4: IpLookupTable::new(),
+            ipv6: IpLookupTable::new(),
+        }
+    }
+
+    /// Returns the table of IPv4 prefixes.
+    pub fn ipv4(&self) -> &IpLookupTable<Ipv4Addr, T> {
+        &self.ipv4
+    }
+
+    /// Returns the table of IPv4 prefixes, mutably.
+    pub fn ipv4_mut(&mut self) -> &mut IpLookupTable<Ipv4Addr, T> {
+        &mut self.ipv4
+    }
+
+    /// Returns the table of IPv6 prefixes.
+    pub fn ipv6(&self) -> &IpLookupTable<Ipv6Addr, T> {
+        &self.ipv6
+    }
+
+    /// Returns the table of IPv6 prefixes, mutably.
+    pub fn ipv6_mut(&mut self) -> &mut IpLookupTable<Ipv6Addr, T> {
+        &mut self.ipv6
+    }
+
+    /// Returns the number of prefixes in the table, of both address families.
+    pub fn len(&self) -> usize {
+        self.ipv4.len() + self.ipv6.len()
+    }
+
+    /// Returns true if the table is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Insert a value for the prefix designated by ip and masklen. If prefix
+    /// existed previously, the old value is returned.
+    ///
+    /// # Panics
+    ///
+    /// Panics if prefix has bits set to the right of mask, or if masklen
+    /// exceeds the number of bits in the address.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use treebitmap::DualStackTable;
+    /// use std::net::{IpAddr, Ipv4Addr};
+    ///
+    /// let mut table = DualStackTable::new();
+    /// let prefix = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 0));
+    ///
+    /// assert_eq!(table.insert(prefix, 8, "foo"), None);
+    /// assert_eq!(table.insert(prefix, 8, "bar"), Some("foo"));
+    /// assert_eq!(table.ipv4().exact_match(Ipv4Addr::new(10, 0, 0, 0), 8), Some(&"bar"));
+    /// ```
+    pub fn insert(&mut self, ip: IpAddr, masklen: u32, value: T) -> Option<T> {
+        match ip {
+            IpAddr::V4(ip) => self.ipv4.insert(ip, masklen, value),
+            IpAddr::V6(ip) => self.ipv6.insert(ip, masklen, value),
+        }
+    }
+
+    /// Insert a value for the prefix designated by ip and masklen. If prefix
+    /// existed previously, the old value is returned. Returns an error, leaving
+    /// the table unchanged, if the prefix is invalid or memory could not be
+    /// allocated.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use treebitmap::{DualStackTable, TableError};
+    ///
+    /// let mut table = DualStackTable::new();
+    ///
+    /// assert_eq!(table.try_insert("2001:db8::".parse().unwrap(), 32, "foo"), Ok(None));
+    /// assert_eq!(
+    ///     table.try_insert("10.0.0.0".parse().unwrap(), 33, "bar"),
+    ///     Err(TableError::MasklenOutOfRange)
+    /// );
+    /// ```
+    pub fn try_insert(
+        &mut self,
+        ip: IpAddr,
+        masklen: u32,
+        value: T,
+    ) -> Result<Option<T>, TableError> {
+        match ip {
+            IpAddr::V4(ip) => self.ipv4.try_insert(ip, masklen, value),
+            IpAddr::V6(ip) => self.ipv6.try_insert(ip, masklen, value),
+        }
+    }
+
+    /// Remove an entry from the table. If the prefix existed previously, the
+    /// value is returned.
+    ///
+    /// # Panics
+    ///
+    /// Panics if prefix has bits set to the right of mask, or if masklen
+    /// exceeds the number of bits in the address.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use treebitmap::DualStackTable;
+    /// use std::net::IpAddr;
+    ///
+    /// let mut table = DualStackTable::new();
+    /// let prefix: IpAddr = "2001:db8::".parse().unwrap();
+    /// table.insert(prefix, 32, "foo");
+    ///
+    /// assert_eq!(table.remove(prefix, 32), Some("foo"));
+    /// assert_eq!(table.remove(prefix, 32), None);
+    /// ```
+    pub fn remove(&mut self, ip: IpAddr, masklen: u32) -> Option<T> {
+        match ip {
+            IpAddr::V4(ip) => self.ipv4.remove(ip, masklen),
+            IpAddr::V6(ip) => self.ipv6.remove(ip, masklen),
+        }
+    }
+
+    /// Remove an entry from the table. If the prefix existed previously, the
+    /// value is returned. Returns an error, leaving the table unchanged, if the
+    /// prefix is invalid or memory could not be allocated.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use treebitmap::{DualStackTable, TableError};
+    /// use std::net::IpAddr;
+    ///
+    /// let mut table = DualStackTable::new();
+    /// let prefix: IpAddr = "2001:db8::".parse().unwrap();
+    /// table.insert(prefix, 32, "foo");
+    ///
+    /// assert_eq!(table.try_remove(prefix, 16), Err(TableError::HostBitsSet));
+    /// assert_eq!(table.try_remove(prefix, 32), Ok(Some("foo")));
+    /// assert_eq!(table.try_remove(prefix, 32), Ok(None));
+    /// ```
+    pub fn try_remove(&mut self, ip: IpAddr, masklen: u32) -> Result<Option<T>, TableError> {
+        match ip {
+            IpAddr::V4(ip) => self.ipv4.try_remove(ip, masklen),
+            IpAddr::V6(ip) => self.ipv6.try_remove(ip, masklen),
+        }
+    }
+
+    /// Perform exact match lookup of ```ip```/```masklen``` and return the
+    /// value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if prefix has bits set to the right of mask, or if masklen
+    /// exceeds the number of bits in the address.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use treebitmap::DualStackTable;
+    /// use std::net::IpAddr;
+    ///
+    /// let mut table = DualStackTable::new();
+    /// let prefix: IpAddr = "10.0.0.0".parse().unwrap();
+    /// table.insert(prefix, 8, "foo");
+    ///
+    /// assert_eq!(table.exact_match(prefix, 8), Some(&"foo"));
+    /// assert_eq!(table.exact_match(prefix, 16), None);
+    /// assert_eq!(table.exact_match("::".parse().unwrap(), 8), None);
+    /// ```
+    pub fn exact_match(&self, ip: IpAddr, masklen: u32) -> Option<&T> {
+        match ip {
+            IpAddr::V4(ip) => self.ipv4.exact_match(ip, masklen),
+            IpAddr::V6(ip) => self.ipv6.exact_match(ip, masklen),
+        }
+    }
+
+    /// Perform exact match lookup of ```ip```/```masklen``` and return the
+    /// value. Returns an error if the prefix is invalid.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use treebitmap::{DualStackTable, TableError};
+    /// use std::net::IpAddr;
+    ///
+    /// let mut table = DualStackTable::new();
+    /// let prefix: IpAddr = "10.0.0.0".parse().unwrap();
+    /// table.insert(prefix, 8, "foo");
+    ///
+    /// assert_eq!(table.try_exact_match(prefix, 8), Ok(Some(&"foo")));
+    /// assert_eq!(table.try_exact_match(prefix, 33), Err(TableError::MasklenOutOfRange));
+    /// assert_eq!(
+    ///     table.try_exact_match("10.1.0.0".parse().unwrap(), 8),
+    ///     Err(TableError::HostBitsSet)
+    /// );
+    /// ```
+    pub fn try_exact_match(&self, ip: IpAddr, masklen: u32) -> Result<Option<&T>, TableError> {
+        match ip {
+            IpAddr::V4(ip) => self.ipv4.try_exact_match(ip, masklen),
+            IpAddr::V6(ip) => self.ipv6.try_exact_match(ip, masklen),
+        }
+    }
+
+    /// Mutable version of exact_match().
+    ///
+    /// # Panics
+    ///
+    /// Panics if prefix has bits set to the right of mask, or if masklen
+    /// exceeds the number of bits in the address.
+    pub fn exact_match_mut(&mut self, ip: IpAddr, masklen: u32) -> Option<&mut T> {
+        match ip {
+            IpAddr::V4(ip) => self.ipv4.exact_match_mut(ip, masklen),
+            IpAddr::V6(ip) => self.ipv6.exact_match_mut(ip, masklen),
+        }
+    }
+
+    /// Perform longest match lookup of ```ip``` and return the best matching
+    /// prefix, designated by ip, masklen, along with its value. Only prefixes
+    /// of the same address family as ```ip``` are considered.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use treebitmap::DualStackTable;
+    /// use std::net::IpAddr;
+    ///
+    /// let mut table = DualStackTable::new();
+    /// let prefix: IpAddr = "10.0.0.0".parse().unwrap();
+    /// table.insert(prefix, 8, "foo");
+    ///
+    /// assert_eq!(table.longest_match("10.1.2.3".parse().unwrap()), Some((prefix, 8, &"foo")));
+    /// assert_eq!(table.longest_match("::ffff:10.1.2.3".parse().unwrap()), None);
+    /// ```
+    pub fn longest_match(&self, ip: IpAddr) -> Option<(IpAddr, u32, &T)> {
+        match ip {
+            IpAddr::V4(ip) => self
+                .ipv4
+                .longest_match(ip)
+                .map(|(ip, masklen, value)| (IpAddr::V4(ip), masklen, value)),
+            IpAddr::V6(ip) => self
+                .ipv6
+                .longest_match(ip)
+                .map(|(ip, masklen, value)| (IpAddr::V6(ip), masklen, value)),
+        }
+    }
+
+    /// Mutable version of longest_match().
+    pub fn longest_match_mut(&mut self, ip: IpAddr) -> Option<(IpAddr, u32, &mut T)> {
+        match ip {
+            IpAddr::V4(ip) => self
+                .ipv4
+                .longest_match_mut(ip)
+                .map(|(ip, masklen, value)| (IpAddr::V4(ip), masklen, value)),
+            IpAddr::V6(ip) => self
+                .ipv6
+                .longest_match_mut(ip)
+                .map(|(ip, masklen, value)| (IpAddr::V6(ip), masklen, value)),
+        }
+    }
+
+    /// Perform longest match lookup of ```ip```, translating IPv6 addresses
+    /// which embed an IPv4 address according to ```translation```. The IPv4
+    /// table is consulted with the embedded address first, and the IPv6 table
+    /// only if no IPv4 prefix matches it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use treebitmap::{DualStackTable, Translation};
+    /// use std::net::IpAddr;
+    ///
+    /// let mut table = DualStackTable::new();
+    /// let prefix: IpAddr = "192.0.2.0".parse().unwrap();
+    /// table.insert(prefix, 24, "foo");
+    ///
+    /// let mapped = "::ffff:192.0.2.1".parse().unwrap();
+    /// let nat64 = "64:ff9b::192.0.2.1".parse().unwrap();
+    /// let six_to_four = "2002:c000:201::1".parse().unwrap();
+    /// for &ip in &[mapped, nat64, six_to_four] {
+    ///     assert_eq!(table.longest_match_translated(ip, Translation::ALL), Some((prefix, 24, &"foo")));
+    ///     assert_eq!(table.longest_match_translated(ip, Translation::NONE), None);
+    /// }
+    /// ```
+    pub fn longest_match_translated(
+        &self,
+        ip: IpAddr,
+        translation: Translation,
+    ) -> Option<(IpAddr, u32, &T)> {
+        if let IpAddr::V6(ipv6) = ip {
+            if let Some(ret) = self.ipv4.longest_match_translated(ipv6, translation) {
+                return Some((IpAddr::V4(ret.0), ret.1, ret.2));
+            }
+        }
+        self.longest_match(ip)
+    }
+
+    /// Returns iterator over prefixes and values. IPv4 prefixes are returned
+    /// before IPv6 prefixes, each family in address order.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use treebitmap::DualStackTable;
+    /// use std::net::IpAddr;
+    ///
+    /// let mut table = DualStackTable::new();
+    /// let v6: IpAddr = "2001:db8::".parse().unwrap();
+    /// let v4: IpAddr = "10.0.0.0".parse().unwrap();
+    /// table.insert(v6, 32, 1);
+    /// table.insert(v4, 8, 2);
+    ///
+    /// let mut iter = table.iter();
+    /// assert_eq!(iter.next(), Some((v4, 8, &2)));
+    /// assert_eq!(iter.next(), Some((v6, 32, &1)));
+    /// assert_eq!(iter.next(), None);
+    /// ```
+    pub fn iter(&self) -> DualStackIter<'_, T> {
+        DualStackIter {
+            ipv4: self.ipv4.iter(),
+            ipv6: self.ipv6.iter(),
+        }
+    }
+
+    /// Mutable version of iter().
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use treebitmap::DualStackTable;
+    ///
+    /// let mut table = DualStackTable::new();
+    /// table.insert("10.0.0.0".parse().unwrap(), 8, 1);
+    /// table.insert("2001:db8::".parse().unwrap(), 32, 2);
+    ///
+    /// for (_ip, _masklen, value) in table.iter_mut() {
+    ///     *value += 10;
+    /// }
+    /// assert_eq!(table.iter().map(|(_, _, &v)| v).collect::<Vec<_>>(), vec![11, 12]);
+    /// ```
+    pub fn iter_mut(&mut self) -> DualStackIterMut<'_, T> {
+        DualStackIterMut {
+            ipv4: self.ipv4.iter_mut(),
+            ipv6: self.ipv6.iter_mut(),
+        }
+    }
+}
+
+impl<T> IpLookupTable<Ipv4Addr, T> {
+    /// Perform longest match lookup of the IPv4 address embedded in ```ip```
+    /// according to ```translation```. Returns None if ```ip``` does not embed
+    /// an IPv4 address, or no prefix matches it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use treebitmap::{IpLookupTable, Translation};
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let mut table = IpLookupTable::new();
+    /// let prefix = Ipv4Addr::new(192, 0, 2, 0);
+    /// table.insert(prefix, 24, "foo");
+    ///
+    /// let nat64 = "64:ff9b::c000:201".parse().unwrap();
+    /// assert_eq!(table.longest_match_translated(nat64, Translation::ALL), Some((prefix, 24, &"foo")));
+    ///
+    /// let translation = Translation { nat64: false, ..Translation::ALL };
+    /// assert_eq!(table.longest_match_translated(nat64, translation), None);
+    /// ```
+    pub fn longest_match_translated(
+        &self,
+        ip: Ipv6Addr,
+        translation: Translation,
+    ) -> Option<(Ipv4Addr, u32, &T)> {
+        translation
+            .embedded_ipv4(ip)
+            .and_then(|ip| self.longest_match(ip))
+    }
+}
+
+/// Kinds of IPv6 addresses embedding an IPv4 address, which translation-aware
+/// lookups replace by the IPv4 address.
+///
+/// # Example
+///
+/// ```
+/// use treebitmap::Translation;
+/// use std::net::Ipv4Addr;
+///
+/// let translation = Translation { six_to_four: false, ..Translation::ALL };
+/// let ip = Ipv4Addr::new(192, 0, 2, 1);
+/// assert_eq!(translation.embedded_ipv4("::ffff:192.0.2.1".parse().unwrap()), Some(ip));
+/// assert_eq!(translation.embedded_ipv4("2002:c000:201::".parse().unwrap()), None);
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Translation {
+    /// IPv4-mapped addresses in ```::ffff:0:0/96```, as defined in RFC 4291.
+    pub ipv4_mapped: bool,
+    /// Addresses in the well-known NAT64 prefix ```64:ff9b::/96```, as defined
+    /// in RFC 6052.
+    pub nat64: bool,
+    /// 6to4 addresses in ```2002::/16```, as defined in RFC 3056, embedding the
+    /// IPv4 address in the 32 bits following the prefix.
+    pub six_to_four: bool,
+}
+
+impl Translation {
+    /// No translation.
+    pub const NONE: Translation = Translation {
+        ipv4_mapped: false,
+        nat64: false,
+        six_to_four: false,
+    };
+
+    /// All supported translations.
+    pub const ALL: Translation = Translation {
+        ipv4_mapped: true,
+        nat64: true,
+        six_to_four: true,
+    };
+
+    /// Returns the IPv4 address embedded in ```ip```, if it is of one of the
+    /// enabled kinds.
+    pub fn embedded_ipv4(&self, ip: Ipv6Addr) -> Option<Ipv4Addr> {
+        let s = ip.segments();
+        let (high, low) = match s {
+            [0, 0, 0, 0, 0, 0xffff, high, low] if self.ipv4_mapped => (high, low),
+            [0x64, 0xff9b, 0, 0, 0, 0, high, low] if self.nat64 => (high, low),
+            [0x2002, high, low, ..] if self.six_to_four => (high, low),
+            _ => return None,
+        };
+        Some(Ipv4Addr::from(u32::from(high) << 16 | u32::from(low)))
+    }
+}
+
+impl<T> Default for DualStackTable<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Clone for DualStackTable<T>
+where
+    T: Clone,
+{
+    fn clone(&self) -> Self {
+        DualStackTable {
+            ipv4: self.ipv4.clone(),
+            ipv6: self.ipv6.clone(),
+        }
+    }
+}
+
+impl<T> PartialEq for DualStackTable<T>
+where
+    T: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.ipv4 == other.ipv4 && self.ipv6 == other.ipv6
+    }
+}
+
+impl<T> Eq for DualStackTable<T> where T: Eq {}
+
+impl<T> Hash for DualStackTable<T>
+where
+    T: Hash,
+{
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.ipv4.hash(state);
+        self.ipv6.hash(state);
+    }
+}
+
+/// Formats the table as a map from prefixes to values, IPv4 prefixes first.
+impl<T> fmt::Debug for DualStackTable<T>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut map = f.debug_map();
+        for (ip, masklen, value) in self.iter() {
+            map.entry(&format_args!("{}/{}", ip, masklen), value);
+        }
+        map.finish()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a DualStackTable<T> {
+    type Item = (IpAddr, u32, &'a T);
+    type IntoIter = DualStackIter<'a, T>;
+
+    fn into_iter(self) -> DualStackIter<'a, T> {
+        self.iter()
+    }
+}
+
+/// Iterator over the prefixes and values of a ```DualStackTable```, IPv4
+/// prefixes first.
+#[doc(hidden)]
+pub struct DualStackIter<'a, T: 'a> {
+    ipv4: Iter<'a, Ipv4Addr, T>,
+    ipv6: Iter<'a, Ipv6Addr, T>,
+}
+
+/// Mutable iterator over the prefixes and values of a ```DualStackTable```,
+/// IPv4 prefixes first.
+#[doc(hidden)]
+pub struct DualStackIterMut<'a, T: 'a> {
+    ipv4: IterMut<'a, Ipv4Addr, T>,
+    ipv6: IterMut<'a, Ipv6Addr, T>,
+}
+
+impl<'a, T: 'a> Iterator for DualStackIter<'a, T> {
+    type Item = (IpAddr, u32, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.ipv4.next() {
+            Some((ip, masklen, value)) => Some((IpAddr::V4(ip), masklen, value)),
+            None => self
+                .ipv6
+                .next()
+                .map(|(ip, masklen, value)| (IpAddr::V6(ip), masklen, value)),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.ipv4.len() + self.ipv6.len();
+        (len, Some(len))
+    }
+}
+
+impl<'a, T: 'a> DoubleEndedIterator for DualStackIter<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        match self.ipv6.next_back() {
+            Some((ip, masklen, value)) => Some((IpAddr::V6(ip), masklen, value)),
+            None => self
+                .ipv4
+                .next_back()
+                .map(|(ip, masklen, value)| (IpAddr::V4(ip), masklen, value)),
+        }
+    }
+}
+
+impl<'a, T: 'a> ExactSizeIterator for DualStackIter<'a, T> {}
+
+impl<'a, T: 'a> Iterator for DualStackIterMut<'a, T> {
+    type Item = (IpAddr, u32, &'a mut T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.ipv4.next() {
+            Some((ip, masklen, value)) => Some((IpAddr::V4(ip), masklen, value)),
+            None => self
+                .ipv6
+                .next()
+                .map(|(ip, masklen, value)| (IpAddr::V6(ip), masklen, value)),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.ipv4.len() + self.ipv6.len();
+        (len, Some(len))
+    }
+}
+
+impl<'a, T: 'a> DoubleEndedIterator for DualStackIterMut<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        match self.ipv6.next_back() {
+            Some((ip, masklen, value)) => Some((IpAddr::V6(ip), masklen, value)),
+            None => self
+                .ipv4
+                .next_back()
+                .map(|(ip, masklen, value)| (IpAddr::V4(ip), masklen, value)),
+        }
+    }
+}
+
+impl<'a, T: 'a> ExactSizeIterator for DualStackIterMut<'a, T> {}
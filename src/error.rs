@@ -0,0 +1,32 @@
+// Copyright 2016 Hroi Sigurdsson
+//
+// Licensed under the MIT license <LICENSE-MIT or http://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed except according to those terms.
+
+#[cfg(not(feature = "alloc"))]
+use std::error::Error;
+use std::fmt;
+
+/// Error returned by the fallible methods of ```IpLookupTable```.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TableError {
+    /// The address has bits set to the right of mask.
+    HostBitsSet,
+    /// The mask length exceeds the number of bits in the address.
+    MasklenOutOfRange,
+    /// Memory for the table could not be allocated.
+    AllocationFailed,
+}
+
+impl fmt::Display for TableError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match *self {
+            TableError::HostBitsSet => "prefix has bits set to the right of mask",
+            TableError::MasklenOutOfRange => "mask length exceeds the bits of the address",
+            TableError::AllocationFailed => "memory allocation failed",
+        })
+    }
+}
+
+#[cfg(not(feature = "alloc"))]
+impl Error for TableError {}
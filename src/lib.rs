@@ -21,16 +21,37 @@
 #[cfg(feature = "alloc")]
 extern crate alloc;
 #[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+#[cfg(feature = "alloc")]
 use core as std;
 
+use std::cmp;
+use std::fmt;
+use std::hash::{Hash, Hasher};
 use std::marker::PhantomData;
+use std::ops::Index;
 
-mod tree_bitmap;
+pub mod tree_bitmap;
 use tree_bitmap::TreeBitmap;
+pub use tree_bitmap::{TrieChildren, TrieCursor, TrieResults};
 
 pub mod address;
 use address::Address;
 
+mod aggregate;
+pub use aggregate::{Aggregate, AggregateTable, Count};
+
+mod prefix;
+pub use prefix::{Prefix, PrefixIterator, PrefixParseError, Prefixes};
+
+mod error;
+pub use error::TableError;
+
+#[cfg(not(feature = "alloc"))]
+mod dual_stack;
+#[cfg(not(feature = "alloc"))]
+pub use dual_stack::{DualStackIter, DualStackIterMut, DualStackTable, Translation};
+
 #[cfg(feature = "alloc")]
 pub use address::addr::*;
 
@@ -80,7 +101,9 @@ where
     ///
     /// # Panics
     ///
-    /// Panics if prefix has bits set to the right of mask.
+    /// Panics if prefix has bits set to the right of mask, or if masklen
+    /// exceeds the number of bits in the address. See `try_insert` for a
+    /// version returning an error instead.
     ///
     /// # Examples
     ///
@@ -90,19 +113,88 @@ where
     ///
     /// let mut table = IpLookupTable::new();
     /// let prefix = Ipv6Addr::new(0x2001, 0xdb8, 0xdead, 0xbeef, 0, 0, 0, 0);
-    /// let masklen = 32;
+    /// let masklen = 64;
     ///
     /// assert_eq!(table.insert(prefix, masklen, "foo"), None);
     /// // Insert duplicate
     /// assert_eq!(table.insert(prefix, masklen, "bar"), Some("foo"));
     /// ```
     pub fn insert(&mut self, ip: A, masklen: u32, value: T) -> Option<T> {
-        self.inner.insert(&ip.nibbles().as_ref(), masklen, value)
+        if let Err(err) = check_prefix(ip, masklen) {
+            panic!("{}", err);
+        }
+        self.inner
+            .insert_unchecked(ip.nibbles().as_ref(), masklen, value)
+    }
+
+    /// Insert a value for the prefix designated by ip and masklen. If prefix
+    /// existed previously, the old value is returned. Returns an error, leaving
+    /// the table unchanged, if the prefix is invalid or memory could not be
+    /// allocated.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use treebitmap::{IpLookupTable, TableError};
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let mut table = IpLookupTable::new();
+    /// let prefix = Ipv4Addr::new(10, 0, 0, 0);
+    ///
+    /// assert_eq!(table.try_insert(prefix, 8, "foo"), Ok(None));
+    /// assert_eq!(table.try_insert(prefix, 8, "bar"), Ok(Some("foo")));
+    /// assert_eq!(table.try_insert(prefix, 33, "baz"), Err(TableError::MasklenOutOfRange));
+    /// assert_eq!(
+    ///     table.try_insert(Ipv4Addr::new(10, 0, 0, 1), 8, "baz"),
+    ///     Err(TableError::HostBitsSet)
+    /// );
+    /// ```
+    pub fn try_insert(&mut self, ip: A, masklen: u32, value: T) -> Result<Option<T>, TableError> {
+        check_prefix(ip, masklen)?;
+        self.inner
+            .try_insert_unchecked(ip.nibbles().as_ref(), masklen, value)
+            .map_err(|_| TableError::AllocationFailed)
+    }
+
+    /// Insert a value for the prefix designated by ip and masklen, clearing any
+    /// bits of ip to the right of mask first. Returns the old value if the
+    /// prefix existed previously, and true if ip had to be normalised.
+    ///
+    /// # Panics
+    ///
+    /// Panics if masklen exceeds the number of bits in the address.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use treebitmap::IpLookupTable;
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let mut table = IpLookupTable::new();
+    ///
+    /// assert_eq!(table.insert_normalized(Ipv4Addr::new(10, 1, 2, 3), 24, "foo"), (None, true));
+    /// assert_eq!(table.insert_normalized(Ipv4Addr::new(10, 1, 2, 0), 24, "bar"), (Some("foo"), false));
+    /// assert_eq!(table.exact_match(Ipv4Addr::new(10, 1, 2, 0), 24), Some(&"bar"));
+    /// ```
+    pub fn insert_normalized(&mut self, ip: A, masklen: u32, value: T) -> (Option<T>, bool) {
+        check_masklen(ip, masklen);
+        let network = ip.mask(masklen);
+        let normalized = network.nibbles().as_ref() != ip.nibbles().as_ref();
+        let ret = self
+            .inner
+            .insert_unchecked(network.nibbles().as_ref(), masklen, value);
+        (ret, normalized)
     }
 
     /// Remove an entry from the lookup table. If the prefix existed previously,
     /// the value is returned.
     ///
+    /// # Panics
+    ///
+    /// Panics if prefix has bits set to the right of mask, or if masklen
+    /// exceeds the number of bits in the address. See `try_remove` for a
+    /// version returning an error instead.
+    ///
     /// # Examples
     ///
     /// ```
@@ -111,7 +203,7 @@ where
     ///
     /// let mut table = IpLookupTable::new();
     /// let prefix = Ipv6Addr::new(0x2001, 0xdb8, 0xdead, 0xbeef, 0, 0, 0, 0);
-    /// let masklen = 32;
+    /// let masklen = 64;
     /// table.insert(prefix, masklen, "foo");
     ///
     /// assert_eq!(table.remove(prefix, masklen), Some("foo"));
@@ -119,12 +211,309 @@ where
     /// assert_eq!(table.remove(prefix, masklen), None);
     /// ```
     pub fn remove(&mut self, ip: A, masklen: u32) -> Option<T> {
-        self.inner.remove(&ip.nibbles().as_ref(), masklen)
+        if let Err(err) = check_prefix(ip, masklen) {
+            panic!("{}", err);
+        }
+        self.inner.remove_unchecked(ip.nibbles().as_ref(), masklen)
+    }
+
+    /// Remove an entry from the lookup table. If the prefix existed previously,
+    /// the value is returned. Returns an error, leaving the table unchanged, if
+    /// the prefix is invalid or memory could not be allocated.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use treebitmap::{IpLookupTable, TableError};
+    /// use std::net::Ipv6Addr;
+    ///
+    /// let mut table = IpLookupTable::new();
+    /// let prefix = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0);
+    /// table.insert(prefix, 32, "foo");
+    ///
+    /// assert_eq!(table.try_remove(prefix, 129), Err(TableError::MasklenOutOfRange));
+    /// assert_eq!(table.try_remove(prefix, 16), Err(TableError::HostBitsSet));
+    /// assert_eq!(table.try_remove(prefix, 32), Ok(Some("foo")));
+    /// assert_eq!(table.try_remove(prefix, 32), Ok(None));
+    /// ```
+    pub fn try_remove(&mut self, ip: A, masklen: u32) -> Result<Option<T>, TableError> {
+        check_prefix(ip, masklen)?;
+        self.inner
+            .try_remove_unchecked(ip.nibbles().as_ref(), masklen)
+            .map_err(|_| TableError::AllocationFailed)
+    }
+
+    /// Insert a value for ```prefix```. Bits to the right of mask are ignored.
+    /// If the prefix existed previously, the old value is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use treebitmap::{IpLookupTable, Prefix};
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let mut table = IpLookupTable::new();
+    /// let prefix: Prefix<Ipv4Addr> = "10.0.0.0/8".parse().unwrap();
+    ///
+    /// assert_eq!(table.insert_prefix(prefix, "foo"), None);
+    /// assert_eq!(table.insert_prefix(prefix, "bar"), Some("foo"));
+    /// assert_eq!(table.exact_match(Ipv4Addr::new(10, 0, 0, 0), 8), Some(&"bar"));
+    /// ```
+    pub fn insert_prefix(&mut self, prefix: Prefix<A>, value: T) -> Option<T> {
+        self.insert(prefix.network(), prefix.len(), value)
+    }
+
+    /// Remove ```prefix``` from the lookup table. Bits to the right of mask are
+    /// ignored. If the prefix existed previously, the value is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use treebitmap::{IpLookupTable, Prefix};
+    /// use std::net::Ipv6Addr;
+    ///
+    /// let mut table = IpLookupTable::new();
+    /// let prefix: Prefix<Ipv6Addr> = "2001:db8::/32".parse().unwrap();
+    /// table.insert_prefix(prefix, "foo");
+    ///
+    /// assert_eq!(table.remove_prefix(prefix), Some("foo"));
+    /// assert_eq!(table.remove_prefix(prefix), None);
+    /// ```
+    pub fn remove_prefix(&mut self, prefix: Prefix<A>) -> Option<T> {
+        self.remove(prefix.network(), prefix.len())
+    }
+
+    /// Returns a reference to the value stored for ```prefix```. Bits to the
+    /// right of mask are ignored.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use treebitmap::{IpLookupTable, Prefix};
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let mut table = IpLookupTable::new();
+    /// table.insert(Ipv4Addr::new(10, 0, 0, 0), 8, "foo");
+    ///
+    /// assert_eq!(table.get("10.0.0.0/8".parse().unwrap()), Some(&"foo"));
+    /// assert_eq!(table.get("10.0.0.0/16".parse().unwrap()), None);
+    /// ```
+    pub fn get(&self, prefix: Prefix<A>) -> Option<&T> {
+        self.exact_match(prefix.network(), prefix.len())
+    }
+
+    /// Mutable version of get().
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use treebitmap::{IpLookupTable, Prefix};
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let mut table = IpLookupTable::new();
+    /// let prefix: Prefix<Ipv4Addr> = "10.0.0.0/8".parse().unwrap();
+    /// table.insert_prefix(prefix, 1);
+    ///
+    /// if let Some(value) = table.get_mut(prefix) {
+    ///     *value += 1;
+    /// }
+    /// assert_eq!(table.get(prefix), Some(&2));
+    /// ```
+    pub fn get_mut(&mut self, prefix: Prefix<A>) -> Option<&mut T> {
+        self.exact_match_mut(prefix.network(), prefix.len())
+    }
+
+    /// Perform longest match lookup of `ip` and return the best matching
+    /// prefix along with its value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use treebitmap::{IpLookupTable, Prefix};
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let mut table = IpLookupTable::new();
+    /// let prefix: Prefix<Ipv4Addr> = "10.0.0.0/8".parse().unwrap();
+    /// table.insert_prefix(prefix, "foo");
+    ///
+    /// assert_eq!(table.lookup(Ipv4Addr::new(10, 1, 2, 3)), Some((prefix, &"foo")));
+    /// assert_eq!(table.lookup(Ipv4Addr::new(11, 1, 2, 3)), None);
+    /// ```
+    pub fn lookup(&self, ip: A) -> Option<(Prefix<A>, &T)> {
+        self.longest_match(ip)
+            .map(|(ip, masklen, value)| (Prefix::new(ip, masklen), value))
+    }
+
+    /// Remove the prefixes making up the minimal CIDR decomposition of the
+    /// address range from `start` to `end` inclusive, as inserted by
+    /// `insert_range`. Returns the removed prefixes and values, in address
+    /// order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use treebitmap::IpLookupTable;
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let mut table = IpLookupTable::new();
+    /// let start = Ipv4Addr::new(10, 0, 0, 0);
+    /// let end = Ipv4Addr::new(10, 0, 2, 255);
+    /// table.insert_range(start, end, "foo");
+    ///
+    /// let removed = table.remove_range(start, end);
+    /// assert_eq!(removed, vec![
+    ///     (Ipv4Addr::new(10, 0, 0, 0), 23, "foo"),
+    ///     (Ipv4Addr::new(10, 0, 2, 0), 24, "foo"),
+    /// ]);
+    /// assert!(table.is_empty());
+    /// ```
+    pub fn remove_range(&mut self, start: A, end: A) -> Vec<(A, u32, T)> {
+        range_prefixes(start, end)
+            .into_iter()
+            .filter_map(|(ip, masklen)| self.remove(ip, masklen).map(|v| (ip, masklen, v)))
+            .collect()
+    }
+
+    /// Remove the prefix designated by ip and masklen along with all more
+    /// specific prefixes contained within it. Returns the number of prefixes
+    /// removed. Bits to the right of mask are ignored.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use treebitmap::IpLookupTable;
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let mut table = IpLookupTable::new();
+    /// table.insert(Ipv4Addr::new(10, 0, 0, 0), 8, 1);
+    /// table.insert(Ipv4Addr::new(10, 1, 0, 0), 16, 2);
+    /// table.insert(Ipv4Addr::new(10, 1, 2, 0), 24, 3);
+    /// table.insert(Ipv4Addr::new(10, 2, 0, 0), 16, 4);
+    ///
+    /// assert_eq!(table.remove_subtree(Ipv4Addr::new(10, 1, 0, 0), 16), 2);
+    /// assert_eq!(table.len(), 2);
+    /// assert_eq!(
+    ///     table.longest_match(Ipv4Addr::new(10, 1, 2, 3)),
+    ///     Some((Ipv4Addr::new(10, 0, 0, 0), 8, &1))
+    /// );
+    /// ```
+    pub fn remove_subtree(&mut self, ip: A, masklen: u32) -> usize {
+        check_masklen(ip, masklen);
+        self.inner
+            .remove_subtree_unchecked(ip.nibbles().as_ref(), masklen)
+    }
+
+    /// Remove the prefix designated by ip and masklen along with all more
+    /// specific prefixes contained within it, and return an iterator over the
+    /// removed prefixes and values in address order. Bits to the right of
+    /// mask are ignored.
+    ///
+    /// The prefixes are removed from the table even if the iterator is not
+    /// consumed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use treebitmap::IpLookupTable;
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let mut table = IpLookupTable::new();
+    /// table.insert(Ipv4Addr::new(10, 0, 0, 0), 8, "foo");
+    /// table.insert(Ipv4Addr::new(10, 1, 0, 0), 16, "bar");
+    /// table.insert(Ipv4Addr::new(10, 1, 2, 0), 24, "baz");
+    ///
+    /// let mut drain = table.drain_prefix(Ipv4Addr::new(10, 1, 0, 0), 16);
+    /// assert_eq!(drain.next(), Some((Ipv4Addr::new(10, 1, 0, 0), 16, "bar")));
+    /// assert_eq!(drain.next(), Some((Ipv4Addr::new(10, 1, 2, 0), 24, "baz")));
+    /// assert_eq!(drain.next(), None);
+    /// assert_eq!(table.len(), 1);
+    /// ```
+    pub fn drain_prefix(&mut self, ip: A, masklen: u32) -> Drain<A, T> {
+        check_masklen(ip, masklen);
+        Drain {
+            inner: self
+                .inner
+                .drain_prefix_unchecked(ip.nibbles().as_ref(), masklen),
+            _addrtype: PhantomData,
+        }
+    }
+
+    /// Retain only the prefixes for which `f` returns `true`. The other
+    /// prefixes are removed from the table in a single traversal.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use treebitmap::IpLookupTable;
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let mut table = IpLookupTable::new();
+    /// table.insert(Ipv4Addr::new(10, 0, 0, 0), 8, 1);
+    /// table.insert(Ipv4Addr::new(10, 1, 0, 0), 16, 2);
+    /// table.insert(Ipv4Addr::new(10, 1, 2, 0), 24, 3);
+    ///
+    /// table.retain(|_ip, masklen, _val| masklen < 24);
+    ///
+    /// assert_eq!(table.len(), 2);
+    /// assert_eq!(
+    ///     table.longest_match(Ipv4Addr::new(10, 1, 2, 3)),
+    ///     Some((Ipv4Addr::new(10, 1, 0, 0), 16, &2))
+    /// );
+    /// ```
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(A, u32, &mut T) -> bool,
+    {
+        self.inner
+            .retain(|nibbles, masklen, value| f(Address::from_nibbles(nibbles), masklen, value))
+    }
+
+    /// Get the entry for the prefix designated by ip and masklen for in-place
+    /// manipulation.
+    ///
+    /// # Panics
+    ///
+    /// Panics if prefix has bits set to the right of mask, or if masklen
+    /// exceeds the number of bits in the address.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use treebitmap::IpLookupTable;
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let mut table = IpLookupTable::new();
+    /// let prefix = Ipv4Addr::new(10, 0, 0, 0);
+    ///
+    /// for _ in 0..3 {
+    ///     *table.entry(prefix, 8).or_insert(0) += 1;
+    /// }
+    /// assert_eq!(table.exact_match(prefix, 8), Some(&3));
+    ///
+    /// table.entry(prefix, 8).and_modify(|count| *count *= 2).or_insert(0);
+    /// assert_eq!(table.exact_match(prefix, 8), Some(&6));
+    /// ```
+    pub fn entry(&mut self, ip: A, masklen: u32) -> Entry<'_, A, T> {
+        if let Err(err) = check_prefix(ip, masklen) {
+            panic!("{}", err);
+        }
+        match self.inner.entry_unchecked(ip.nibbles().as_ref(), masklen) {
+            tree_bitmap::Entry::Occupied(inner) => {
+                Entry::Occupied(OccupiedEntry { inner, ip, masklen })
+            }
+            tree_bitmap::Entry::Vacant(inner) => Entry::Vacant(VacantEntry { inner, ip, masklen }),
+        }
     }
 
     /// Perform exact match lookup of `ip`/`masklen` and return the
     /// value.
     ///
+    /// # Panics
+    ///
+    /// Panics if prefix has bits set to the right of mask, or if masklen
+    /// exceeds the number of bits in the address. See `try_exact_match` for a
+    /// version returning an error instead.
+    ///
     /// # Examples
     ///
     /// ```
@@ -141,7 +530,92 @@ where
     /// assert_eq!(table.exact_match(prefix, 48), None);
     /// ```
     pub fn exact_match(&self, ip: A, masklen: u32) -> Option<&T> {
-        self.inner.exact_match(&ip.nibbles().as_ref(), masklen)
+        if let Err(err) = check_prefix(ip, masklen) {
+            panic!("{}", err);
+        }
+        self.inner
+            .exact_match_unchecked(ip.nibbles().as_ref(), masklen)
+    }
+
+    /// Perform exact match lookup of `ip`/`masklen` and return the value.
+    /// Returns an error if the prefix is invalid.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use treebitmap::{IpLookupTable, TableError};
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let mut table = IpLookupTable::new();
+    /// let prefix = Ipv4Addr::new(10, 0, 0, 0);
+    /// table.insert(prefix, 8, "foo");
+    ///
+    /// assert_eq!(table.try_exact_match(prefix, 8), Ok(Some(&"foo")));
+    /// assert_eq!(table.try_exact_match(prefix, 16), Ok(None));
+    /// assert_eq!(table.try_exact_match(prefix, 40), Err(TableError::MasklenOutOfRange));
+    /// ```
+    pub fn try_exact_match(&self, ip: A, masklen: u32) -> Result<Option<&T>, TableError> {
+        check_prefix(ip, masklen)?;
+        Ok(self.exact_match(ip, masklen))
+    }
+
+    /// Perform exact match lookup of `ip`/`masklen` and return a mutable
+    /// reference to the value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if prefix has bits set to the right of mask, or if masklen
+    /// exceeds the number of bits in the address.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use treebitmap::IpLookupTable;
+    /// use std::net::Ipv6Addr;
+    ///
+    /// let mut table = IpLookupTable::new();
+    /// let prefix = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0);
+    /// let masklen = 32;
+    /// table.insert(prefix, masklen, 1);
+    ///
+    /// if let Some(value) = table.exact_match_mut(prefix, masklen) {
+    ///     *value += 1;
+    /// }
+    /// assert_eq!(table.exact_match(prefix, masklen), Some(&2));
+    /// // differing mask
+    /// assert_eq!(table.exact_match_mut(prefix, 48), None);
+    /// ```
+    pub fn exact_match_mut(&mut self, ip: A, masklen: u32) -> Option<&mut T> {
+        if let Err(err) = check_prefix(ip, masklen) {
+            panic!("{}", err);
+        }
+        self.inner
+            .exact_match_mut_unchecked(ip.nibbles().as_ref(), masklen)
+    }
+
+    /// Returns `true` if the table contains a value for the prefix designated
+    /// by ip and masklen.
+    ///
+    /// # Panics
+    ///
+    /// Panics if prefix has bits set to the right of mask, or if masklen
+    /// exceeds the number of bits in the address.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use treebitmap::IpLookupTable;
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let mut table = IpLookupTable::new();
+    /// let prefix = Ipv4Addr::new(10, 0, 0, 0);
+    /// table.insert(prefix, 8, "foo");
+    ///
+    /// assert!(table.contains(prefix, 8));
+    /// assert!(!table.contains(prefix, 16));
+    /// ```
+    pub fn contains(&self, ip: A, masklen: u32) -> bool {
+        self.exact_match(ip, masklen).is_some()
     }
 
     /// Perform longest match lookup of `ip` and return the best matching
@@ -170,78 +644,1134 @@ where
     /// assert_eq!(result, Some((less_specific, 32, &"foo")));
     /// ```
     pub fn longest_match(&self, ip: A) -> Option<(A, u32, &T)> {
-        match self.inner.longest_match(&ip.nibbles().as_ref()) {
+        match self.inner.longest_match_unchecked(ip.nibbles().as_ref()) {
             Some((bits_matched, value)) => Some((ip.mask(bits_matched), bits_matched, value)),
             None => None,
         }
     }
 
-    /// Returns iterator over prefixes and values.
+    /// Perform longest match lookup of `ip`, considering only prefixes for
+    /// which `f` returns true when called with the mask length and value. The
+    /// lookup does not allocate; `f` is called on the way down the trie, so it
+    /// may see less specific prefixes before more specific ones.
     ///
-    /// # Examples
+    /// # Example
     ///
     /// ```
     /// use treebitmap::IpLookupTable;
-    /// use std::net::Ipv6Addr;
+    /// use std::net::Ipv4Addr;
     ///
     /// let mut table = IpLookupTable::new();
-    /// let less_specific = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0);
-    /// let more_specific = Ipv6Addr::new(0x2001, 0xdb8, 0xdead, 0, 0, 0, 0, 0);
-    /// table.insert(less_specific, 32, "foo");
-    /// table.insert(more_specific, 48, "bar");
+    /// let less_specific = Ipv4Addr::new(10, 0, 0, 0);
+    /// let more_specific = Ipv4Addr::new(10, 1, 0, 0);
+    /// table.insert(less_specific, 8, ("foo", true));
+    /// table.insert(more_specific, 16, ("bar", false));
     ///
-    /// let mut iter = table.iter();
-    /// assert_eq!(iter.next(), Some((less_specific, 32, &"foo")));
-    /// assert_eq!(iter.next(), Some((more_specific, 48, &"bar")));
-    /// assert_eq!(iter.next(), None);
+    /// let lookupip = Ipv4Addr::new(10, 1, 2, 3);
+    /// let result = table.longest_match_where(lookupip, |_, &(_, up)| up);
+    /// assert_eq!(result, Some((less_specific, 8, &("foo", true))));
+    ///
+    /// let result = table.longest_match_where(lookupip, |masklen, _| masklen > 8);
+    /// assert_eq!(result, Some((more_specific, 16, &("bar", false))));
+    ///
+    /// let result = table.longest_match_where(lookupip, |_, _| false);
+    /// assert_eq!(result, None);
     /// ```
-    pub fn iter(&self) -> Iter<A, T> {
-        Iter {
-            inner: self.inner.iter(),
-            _addrtype: PhantomData,
+    pub fn longest_match_where<F>(&self, ip: A, f: F) -> Option<(A, u32, &T)>
+    where
+        F: FnMut(u32, &T) -> bool,
+    {
+        match self
+            .inner
+            .longest_match_where_unchecked(ip.nibbles().as_ref(), f)
+        {
+            Some((bits_matched, value)) => Some((ip.mask(bits_matched), bits_matched, value)),
+            None => None,
         }
     }
 
-    /// Mutable version of iter().
+    /// Perform longest match lookup of `ip`, only considering prefixes with a
+    /// mask length between `min_len` and `max_len` inclusive.
     ///
-    /// # Examples
+    /// # Example
     ///
     /// ```
     /// use treebitmap::IpLookupTable;
-    /// use std::net::Ipv6Addr;
+    /// use std::net::Ipv4Addr;
     ///
-    /// let x: Ipv6Addr = "2001:db8:100::".parse().unwrap();
-    /// let y: Ipv6Addr = "2001:db8:100::".parse().unwrap();
-    /// let z: Ipv6Addr = "2001:db8:102::".parse().unwrap();
     /// let mut table = IpLookupTable::new();
+    /// let default = Ipv4Addr::new(0, 0, 0, 0);
+    /// let net = Ipv4Addr::new(10, 0, 0, 0);
+    /// let host = Ipv4Addr::new(10, 0, 0, 1);
+    /// table.insert(default, 0, "default");
+    /// table.insert(net, 8, "net");
+    /// table.insert(host, 32, "host");
     ///
-    /// table.insert(x, 48, 1);
-    /// table.insert(y, 56, 2);
-    /// table.insert(z, 56, 3);
+    /// let result = table.longest_match_within(host, 8, 24);
+    /// assert_eq!(result, Some((net, 8, &"net")));
     ///
-    /// for (_ip, _mask, val) in table.iter_mut() {
-    ///     *val += 10;
-    /// }
+    /// let result = table.longest_match_within(Ipv4Addr::new(192, 168, 1, 1), 8, 24);
+    /// assert_eq!(result, None);
     ///
-    /// assert_eq!(table.exact_match(x, 48), Some(&11));
-    /// assert_eq!(table.exact_match(y, 56), Some(&12));
-    /// assert_eq!(table.exact_match(z, 56), Some(&13));
+    /// let result = table.longest_match_within(host, 0, 32);
+    /// assert_eq!(result, table.longest_match(host));
     /// ```
+    pub fn longest_match_within(&self, ip: A, min_len: u32, max_len: u32) -> Option<(A, u32, &T)> {
+        match self
+            .inner
+            .longest_match_within_unchecked(ip.nibbles().as_ref(), min_len, max_len)
+        {
+            Some((bits_matched, value)) => Some((ip.mask(bits_matched), bits_matched, value)),
+            None => None,
+        }
+    }
 
-    pub fn iter_mut(&mut self) -> IterMut<A, T> {
+    /// Mutable version of longest_match().
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use treebitmap::IpLookupTable;
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let mut table = IpLookupTable::new();
+    /// let less_specific = Ipv4Addr::new(10, 0, 0, 0);
+    /// let more_specific = Ipv4Addr::new(10, 1, 0, 0);
+    /// table.insert(less_specific, 8, 0);
+    /// table.insert(more_specific, 16, 0);
+    ///
+    /// if let Some((_ip, _masklen, hits)) = table.longest_match_mut(Ipv4Addr::new(10, 1, 2, 3)) {
+    ///     *hits += 1;
+    /// }
+    /// assert_eq!(table.exact_match(less_specific, 8), Some(&0));
+    /// assert_eq!(table.exact_match(more_specific, 16), Some(&1));
+    /// ```
+    pub fn longest_match_mut(&mut self, ip: A) -> Option<(A, u32, &mut T)> {
+        match self
+            .inner
+            .longest_match_mut_unchecked(ip.nibbles().as_ref())
+        {
+            Some((bits_matched, value)) => Some((ip.mask(bits_matched), bits_matched, value)),
+            None => None,
+        }
+    }
+
+    /// Perform shortest match lookup of `ip` and return the least specific
+    /// prefix covering it, designated by ip, masklen, along with its value.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use treebitmap::IpLookupTable;
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let mut table = IpLookupTable::new();
+    /// let less_specific = Ipv4Addr::new(10, 0, 0, 0);
+    /// let more_specific = Ipv4Addr::new(10, 1, 0, 0);
+    /// table.insert(less_specific, 8, "foo");
+    /// table.insert(more_specific, 16, "bar");
+    ///
+    /// let result = table.shortest_match(Ipv4Addr::new(10, 1, 2, 3));
+    /// assert_eq!(result, Some((less_specific, 8, &"foo")));
+    ///
+    /// let result = table.shortest_match(Ipv4Addr::new(192, 168, 1, 1));
+    /// assert_eq!(result, None);
+    /// ```
+    pub fn shortest_match(&self, ip: A) -> Option<(A, u32, &T)> {
+        match self.inner.shortest_match_unchecked(ip.nibbles().as_ref()) {
+            Some((bits_matched, value)) => Some((ip.mask(bits_matched), bits_matched, value)),
+            None => None,
+        }
+    }
+
+    /// Perform longest match lookup of the prefix designated by ip and
+    /// masklen, and return the most specific prefix covering it, designated by
+    /// ip, masklen, along with its value. The prefix itself is considered
+    /// covering, so an exact match is returned if present. Bits to the right
+    /// of mask are ignored.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use treebitmap::IpLookupTable;
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let mut table = IpLookupTable::new();
+    /// table.insert(Ipv4Addr::new(10, 0, 0, 0), 8, "foo");
+    /// table.insert(Ipv4Addr::new(10, 1, 2, 0), 24, "bar");
+    ///
+    /// let result = table.longest_match_prefix(Ipv4Addr::new(10, 1, 2, 0), 23);
+    /// assert_eq!(result, Some((Ipv4Addr::new(10, 0, 0, 0), 8, &"foo")));
+    ///
+    /// let result = table.longest_match_prefix(Ipv4Addr::new(10, 1, 2, 0), 24);
+    /// assert_eq!(result, Some((Ipv4Addr::new(10, 1, 2, 0), 24, &"bar")));
+    /// ```
+    pub fn longest_match_prefix(&self, ip: A, masklen: u32) -> Option<(A, u32, &T)> {
+        check_masklen(ip, masklen);
+        match self
+            .inner
+            .longest_match_prefix_unchecked(ip.nibbles().as_ref(), masklen)
+        {
+            Some((bits_matched, value)) => Some((ip.mask(bits_matched), bits_matched, value)),
+            None => None,
+        }
+    }
+
+    /// Returns the most specific prefix strictly covering the prefix
+    /// designated by ip and masklen, excluding the prefix itself, along with
+    /// its value. Bits to the right of mask are ignored.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use treebitmap::IpLookupTable;
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let mut table = IpLookupTable::new();
+    /// table.insert(Ipv4Addr::new(10, 0, 0, 0), 8, "foo");
+    /// table.insert(Ipv4Addr::new(10, 1, 0, 0), 16, "bar");
+    ///
+    /// let result = table.parent(Ipv4Addr::new(10, 1, 0, 0), 16);
+    /// assert_eq!(result, Some((Ipv4Addr::new(10, 0, 0, 0), 8, &"foo")));
+    /// assert_eq!(table.parent(Ipv4Addr::new(10, 0, 0, 0), 8), None);
+    /// ```
+    pub fn parent(&self, ip: A, masklen: u32) -> Option<(A, u32, &T)> {
+        check_masklen(ip, masklen);
+        match self.inner.parent_unchecked(ip.nibbles().as_ref(), masklen) {
+            Some((bits_matched, value)) => Some((ip.mask(bits_matched), bits_matched, value)),
+            None => None,
+        }
+    }
+
+    /// Returns iterator over the immediate more specific prefixes of the
+    /// prefix designated by ip and masklen, i.e. the prefixes it covers that
+    /// are not covered by another prefix in between. The prefix itself does
+    /// not need to be present in the table. Bits to the right of mask are
+    /// ignored.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use treebitmap::IpLookupTable;
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let mut table = IpLookupTable::new();
+    /// table.insert(Ipv4Addr::new(10, 0, 0, 0), 8, 1);
+    /// table.insert(Ipv4Addr::new(10, 1, 0, 0), 16, 2);
+    /// table.insert(Ipv4Addr::new(10, 1, 1, 0), 24, 3);
+    /// table.insert(Ipv4Addr::new(10, 2, 0, 0), 16, 4);
+    ///
+    /// let children: Vec<_> = table.children(Ipv4Addr::new(10, 0, 0, 0), 8).collect();
+    /// assert_eq!(children, vec![
+    ///     (Ipv4Addr::new(10, 1, 0, 0), 16, &2),
+    ///     (Ipv4Addr::new(10, 2, 0, 0), 16, &4),
+    /// ]);
+    /// ```
+    pub fn children(&self, ip: A, masklen: u32) -> Children<'_, A, T> {
+        check_masklen(ip, masklen);
+        Children {
+            inner: self
+                .inner
+                .children_unchecked(ip.nibbles().as_ref(), masklen),
+            _addrtype: PhantomData,
+        }
+    }
+
+    /// Returns the first prefix after the prefix designated by ip and masklen
+    /// in address order, along with its value. The prefixes are ordered by
+    /// network address, then by mask length. The prefix itself does not need
+    /// to be present in the table. Bits to the right of mask are ignored.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use treebitmap::IpLookupTable;
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let mut table = IpLookupTable::new();
+    /// table.insert(Ipv4Addr::new(10, 0, 0, 0), 8, 1);
+    /// table.insert(Ipv4Addr::new(10, 0, 0, 0), 16, 2);
+    /// table.insert(Ipv4Addr::new(10, 2, 0, 0), 16, 3);
+    ///
+    /// let result = table.next_after(Ipv4Addr::new(10, 0, 0, 0), 8);
+    /// assert_eq!(result, Some((Ipv4Addr::new(10, 0, 0, 0), 16, &2)));
+    /// let result = table.next_after(Ipv4Addr::new(10, 1, 0, 0), 16);
+    /// assert_eq!(result, Some((Ipv4Addr::new(10, 2, 0, 0), 16, &3)));
+    /// assert_eq!(table.next_after(Ipv4Addr::new(10, 2, 0, 0), 16), None);
+    /// ```
+    pub fn next_after(&self, ip: A, masklen: u32) -> Option<(A, u32, &T)> {
+        check_masklen(ip, masklen);
+        match self
+            .inner
+            .next_after_unchecked(ip.nibbles().as_ref(), masklen)
+        {
+            Some((nibbles, masklen, value)) => {
+                Some((Address::from_nibbles(&nibbles[..]), masklen, value))
+            }
+            None => None,
+        }
+    }
+
+    /// Returns the last prefix before the prefix designated by ip and masklen
+    /// in address order, along with its value. The prefixes are ordered by
+    /// network address, then by mask length. The prefix itself does not need
+    /// to be present in the table. Bits to the right of mask are ignored.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use treebitmap::IpLookupTable;
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let mut table = IpLookupTable::new();
+    /// table.insert(Ipv4Addr::new(10, 0, 0, 0), 8, 1);
+    /// table.insert(Ipv4Addr::new(10, 0, 0, 0), 16, 2);
+    /// table.insert(Ipv4Addr::new(10, 2, 0, 0), 16, 3);
+    ///
+    /// let result = table.prev_before(Ipv4Addr::new(10, 2, 0, 0), 16);
+    /// assert_eq!(result, Some((Ipv4Addr::new(10, 0, 0, 0), 16, &2)));
+    /// let result = table.prev_before(Ipv4Addr::new(10, 0, 0, 0), 16);
+    /// assert_eq!(result, Some((Ipv4Addr::new(10, 0, 0, 0), 8, &1)));
+    /// assert_eq!(table.prev_before(Ipv4Addr::new(10, 0, 0, 0), 8), None);
+    /// ```
+    pub fn prev_before(&self, ip: A, masklen: u32) -> Option<(A, u32, &T)> {
+        check_masklen(ip, masklen);
+        match self
+            .inner
+            .prev_before_unchecked(ip.nibbles().as_ref(), masklen)
+        {
+            Some((nibbles, masklen, value)) => {
+                Some((Address::from_nibbles(&nibbles[..]), masklen, value))
+            }
+            None => None,
+        }
+    }
+
+    /// Returns the first prefix in address order, along with its value.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use treebitmap::IpLookupTable;
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let mut table = IpLookupTable::new();
+    /// assert_eq!(table.first(), None);
+    /// table.insert(Ipv4Addr::new(10, 1, 0, 0), 16, 2);
+    /// table.insert(Ipv4Addr::new(10, 0, 0, 0), 8, 1);
+    /// assert_eq!(table.first(), Some((Ipv4Addr::new(10, 0, 0, 0), 8, &1)));
+    /// ```
+    pub fn first(&self) -> Option<(A, u32, &T)> {
+        match self.inner.first() {
+            Some((nibbles, masklen, value)) => {
+                Some((Address::from_nibbles(&nibbles[..]), masklen, value))
+            }
+            None => None,
+        }
+    }
+
+    /// Returns the last prefix in address order, along with its value.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use treebitmap::IpLookupTable;
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let mut table = IpLookupTable::new();
+    /// assert_eq!(table.last(), None);
+    /// table.insert(Ipv4Addr::new(10, 1, 0, 0), 16, 2);
+    /// table.insert(Ipv4Addr::new(10, 0, 0, 0), 8, 1);
+    /// assert_eq!(table.last(), Some((Ipv4Addr::new(10, 1, 0, 0), 16, &2)));
+    /// ```
+    pub fn last(&self) -> Option<(A, u32, &T)> {
+        match self.inner.last() {
+            Some((nibbles, masklen, value)) => {
+                Some((Address::from_nibbles(&nibbles[..]), masklen, value))
+            }
+            None => None,
+        }
+    }
+
+    /// Removes the first prefix in address order from the table, returning
+    /// it along with its value.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use treebitmap::IpLookupTable;
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let mut table = IpLookupTable::new();
+    /// table.insert(Ipv4Addr::new(10, 1, 0, 0), 16, 2);
+    /// table.insert(Ipv4Addr::new(10, 0, 0, 0), 8, 1);
+    /// assert_eq!(table.pop_first(), Some((Ipv4Addr::new(10, 0, 0, 0), 8, 1)));
+    /// assert_eq!(table.pop_first(), Some((Ipv4Addr::new(10, 1, 0, 0), 16, 2)));
+    /// assert_eq!(table.pop_first(), None);
+    /// ```
+    pub fn pop_first(&mut self) -> Option<(A, u32, T)> {
+        self.inner
+            .pop_first()
+            .map(|(nibbles, masklen, value)| (Address::from_nibbles(&nibbles[..]), masklen, value))
+    }
+
+    /// Returns a read-only cursor for walking the nodes of the underlying
+    /// trie, starting at the root node. Each node covers 4 bits of the
+    /// address.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use treebitmap::IpLookupTable;
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let mut table = IpLookupTable::new();
+    /// table.insert(Ipv4Addr::new(0, 0, 0, 0), 0, "foo");
+    /// table.insert(Ipv4Addr::new(16, 0, 0, 0), 8, "bar");
+    ///
+    /// let mut cursor = table.trie_cursor();
+    /// assert_eq!(cursor.results().collect::<Vec<_>>(), vec![(0, 0, &"foo")]);
+    /// assert_eq!(cursor.children().collect::<Vec<_>>(), vec![1]);
+    /// assert!(cursor.descend(1));
+    /// assert_eq!(cursor.nibbles(), &[1]);
+    /// assert!(cursor.is_endnode());
+    /// assert_eq!(cursor.results().collect::<Vec<_>>(), vec![(0, 8, &"bar")]);
+    /// assert!(!cursor.descend(0));
+    /// assert!(cursor.ascend());
+    /// assert_eq!(cursor.depth(), 0);
+    /// ```
+    pub fn trie_cursor(&self) -> TrieCursor<'_, T> {
+        self.inner.trie_cursor()
+    }
+
+    /// Returns iterator over prefixes and values. The prefixes are ordered by
+    /// network address, then by mask length. The iterator can also be
+    /// consumed from the back, in reverse order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use treebitmap::IpLookupTable;
+    /// use std::net::Ipv6Addr;
+    ///
+    /// let mut table = IpLookupTable::new();
+    /// let less_specific = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0);
+    /// let more_specific = Ipv6Addr::new(0x2001, 0xdb8, 0xdead, 0, 0, 0, 0, 0);
+    /// table.insert(less_specific, 32, "foo");
+    /// table.insert(more_specific, 48, "bar");
+    ///
+    /// let mut iter = table.iter();
+    /// assert_eq!(iter.len(), 2);
+    /// assert_eq!(iter.next(), Some((less_specific, 32, &"foo")));
+    /// assert_eq!(iter.next(), Some((more_specific, 48, &"bar")));
+    /// assert_eq!(iter.next(), None);
+    ///
+    /// let mut iter = table.iter().rev();
+    /// assert_eq!(iter.next(), Some((more_specific, 48, &"bar")));
+    /// assert_eq!(iter.next(), Some((less_specific, 32, &"foo")));
+    /// assert_eq!(iter.next(), None);
+    /// ```
+    pub fn iter(&self) -> Iter<'_, A, T> {
+        Iter {
+            inner: self.inner.iter(),
+            cursor: IterCursor::at(Address::from_nibbles(&[]), 0),
+        }
+    }
+
+    /// Mutable version of iter().
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use treebitmap::IpLookupTable;
+    /// use std::net::Ipv6Addr;
+    ///
+    /// let x: Ipv6Addr = "2001:db8:100::".parse().unwrap();
+    /// let y: Ipv6Addr = "2001:db8:100::".parse().unwrap();
+    /// let z: Ipv6Addr = "2001:db8:102::".parse().unwrap();
+    /// let mut table = IpLookupTable::new();
+    ///
+    /// table.insert(x, 48, 1);
+    /// table.insert(y, 56, 2);
+    /// table.insert(z, 56, 3);
+    ///
+    /// for (_ip, _mask, val) in table.iter_mut() {
+    ///     *val += 10;
+    /// }
+    ///
+    /// assert_eq!(table.exact_match(x, 48), Some(&11));
+    /// assert_eq!(table.exact_match(y, 56), Some(&12));
+    /// assert_eq!(table.exact_match(z, 56), Some(&13));
+    /// ```
+    pub fn iter_mut(&mut self) -> IterMut<'_, A, T> {
         IterMut {
             inner: self.inner.iter_mut(),
+            cursor: IterCursor::at(Address::from_nibbles(&[]), 0),
+        }
+    }
+
+    /// Returns iterator over the prefix designated by ip and masklen, and all
+    /// more specific prefixes contained within it, along with their values.
+    /// Bits to the right of mask are ignored.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use treebitmap::IpLookupTable;
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let mut table = IpLookupTable::new();
+    /// let covering = Ipv4Addr::new(0, 0, 0, 0);
+    /// let prefix = Ipv4Addr::new(10, 0, 0, 0);
+    /// let more_specific = Ipv4Addr::new(10, 1, 2, 0);
+    /// let unrelated = Ipv4Addr::new(192, 168, 0, 0);
+    /// table.insert(covering, 0, "foo");
+    /// table.insert(prefix, 8, "bar");
+    /// table.insert(more_specific, 24, "baz");
+    /// table.insert(unrelated, 16, "quux");
+    ///
+    /// let mut iter = table.iter_prefix(prefix, 8);
+    /// assert_eq!(iter.next(), Some((prefix, 8, &"bar")));
+    /// assert_eq!(iter.next(), Some((more_specific, 24, &"baz")));
+    /// assert_eq!(iter.next(), None);
+    /// ```
+    pub fn iter_prefix(&self, ip: A, masklen: u32) -> Range<'_, A, T> {
+        check_masklen(ip, masklen);
+        Range {
+            inner: self
+                .inner
+                .iter_prefix_unchecked(ip.nibbles().as_ref(), masklen),
+            cursor: IterCursor::at(ip.mask(masklen), masklen),
+        }
+    }
+
+    /// Returns iterator over the prefixes intersecting the address range from
+    /// `start` to `end` inclusive, along with their values. This includes the
+    /// prefixes covering the range, the prefixes within it and the prefixes
+    /// partially overlapping it. The prefixes are returned in address order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use treebitmap::IpLookupTable;
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let mut table = IpLookupTable::new();
+    /// let covering = Ipv4Addr::new(10, 0, 0, 0);
+    /// let overlapping = Ipv4Addr::new(10, 0, 0, 0);
+    /// let within = Ipv4Addr::new(10, 0, 2, 0);
+    /// let outside = Ipv4Addr::new(10, 0, 4, 0);
+    /// table.insert(covering, 8, "foo");
+    /// table.insert(overlapping, 24, "bar");
+    /// table.insert(within, 24, "baz");
+    /// table.insert(outside, 24, "quux");
+    ///
+    /// let start = Ipv4Addr::new(10, 0, 0, 17);
+    /// let end = Ipv4Addr::new(10, 0, 3, 200);
+    /// let result: Vec<_> = table.overlapping_range(start, end).collect();
+    /// assert_eq!(result, vec![
+    ///     (covering, 8, &"foo"),
+    ///     (overlapping, 24, &"bar"),
+    ///     (within, 24, &"baz"),
+    /// ]);
+    /// ```
+    pub fn overlapping_range(&self, start: A, end: A) -> Overlapping<'_, A, T> {
+        Overlapping {
+            inner: self
+                .inner
+                .overlapping_range_unchecked(start.nibbles().as_ref(), end.nibbles().as_ref()),
+            _addrtype: PhantomData,
+        }
+    }
+
+    /// Returns iterator over prefixes and values, starting at the prefix
+    /// designated by ip and masklen and continuing in address order to the end
+    /// of the table. The prefix itself does not need to be present in the
+    /// table. Bits to the right of mask are ignored.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use treebitmap::IpLookupTable;
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let mut table = IpLookupTable::new();
+    /// table.insert(Ipv4Addr::new(10, 0, 0, 0), 8, 1);
+    /// table.insert(Ipv4Addr::new(10, 1, 0, 0), 16, 2);
+    /// table.insert(Ipv4Addr::new(10, 2, 0, 0), 16, 3);
+    ///
+    /// let mut iter = table.iter_from(Ipv4Addr::new(10, 1, 0, 0), 16);
+    /// assert_eq!(iter.next(), Some((Ipv4Addr::new(10, 1, 0, 0), 16, &2)));
+    /// assert_eq!(iter.next(), Some((Ipv4Addr::new(10, 2, 0, 0), 16, &3)));
+    /// assert_eq!(iter.next(), None);
+    /// ```
+    pub fn iter_from(&self, ip: A, masklen: u32) -> Range<'_, A, T> {
+        check_masklen(ip, masklen);
+        Range {
+            inner: self
+                .inner
+                .iter_from_unchecked(ip.nibbles().as_ref(), masklen),
+            cursor: IterCursor::at(ip.mask(masklen), masklen),
+        }
+    }
+
+    /// Returns iterator over prefixes and values, resuming in address order
+    /// from a cursor previously obtained from ```Iter::cursor```. Resuming
+    /// continues to the end of the table, also when the cursor was taken from
+    /// an iterator over a single prefix. The table may have been modified in
+    /// the meantime.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use treebitmap::IpLookupTable;
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let mut table = IpLookupTable::new();
+    /// table.insert(Ipv4Addr::new(10, 0, 0, 0), 8, 1);
+    /// table.insert(Ipv4Addr::new(10, 1, 0, 0), 16, 2);
+    ///
+    /// let mut iter = table.iter();
+    /// assert_eq!(iter.next(), Some((Ipv4Addr::new(10, 0, 0, 0), 8, &1)));
+    /// let cursor = iter.cursor();
+    ///
+    /// table.insert(Ipv4Addr::new(10, 2, 0, 0), 16, 3);
+    /// let mut iter = table.iter_from_cursor(cursor);
+    /// assert_eq!(iter.next(), Some((Ipv4Addr::new(10, 1, 0, 0), 16, &2)));
+    /// assert_eq!(iter.next(), Some((Ipv4Addr::new(10, 2, 0, 0), 16, &3)));
+    /// assert_eq!(iter.next(), None);
+    /// ```
+    pub fn iter_from_cursor(&self, cursor: IterCursor<A>) -> Range<'_, A, T> {
+        check_masklen(cursor.ip, cursor.masklen);
+        let nibbles = cursor.ip.nibbles();
+        let inner = if cursor.inclusive {
+            self.inner
+                .iter_from_unchecked(nibbles.as_ref(), cursor.masklen)
+        } else {
+            self.inner
+                .iter_after_unchecked(nibbles.as_ref(), cursor.masklen)
+        };
+        Range { inner, cursor }
+    }
+
+    /// Mutable version of iter_prefix().
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use treebitmap::IpLookupTable;
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let mut table = IpLookupTable::new();
+    /// let prefix = Ipv4Addr::new(10, 0, 0, 0);
+    /// let more_specific = Ipv4Addr::new(10, 1, 2, 0);
+    /// let unrelated = Ipv4Addr::new(192, 168, 0, 0);
+    /// table.insert(prefix, 8, 1);
+    /// table.insert(more_specific, 24, 2);
+    /// table.insert(unrelated, 16, 3);
+    ///
+    /// for (_ip, _mask, val) in table.iter_prefix_mut(prefix, 8) {
+    ///     *val += 10;
+    /// }
+    ///
+    /// assert_eq!(table.exact_match(prefix, 8), Some(&11));
+    /// assert_eq!(table.exact_match(more_specific, 24), Some(&12));
+    /// assert_eq!(table.exact_match(unrelated, 16), Some(&3));
+    /// ```
+    pub fn iter_prefix_mut(&mut self, ip: A, masklen: u32) -> RangeMut<'_, A, T> {
+        check_masklen(ip, masklen);
+        RangeMut {
+            inner: self
+                .inner
+                .iter_prefix_mut_unchecked(ip.nibbles().as_ref(), masklen),
             _addrtype: PhantomData,
         }
     }
+
+    /// Returns iterator over all prefixes matching `ip`, along with their
+    /// values. The prefixes are returned from least to most specific, the last
+    /// one being the result of `longest_match`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use treebitmap::IpLookupTable;
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let mut table = IpLookupTable::new();
+    /// let default = Ipv4Addr::new(0, 0, 0, 0);
+    /// let less_specific = Ipv4Addr::new(10, 0, 0, 0);
+    /// let more_specific = Ipv4Addr::new(10, 1, 2, 0);
+    /// let unrelated = Ipv4Addr::new(192, 168, 0, 0);
+    /// table.insert(default, 0, "foo");
+    /// table.insert(less_specific, 8, "bar");
+    /// table.insert(more_specific, 24, "baz");
+    /// table.insert(unrelated, 16, "quux");
+    ///
+    /// let mut matches = table.matches(Ipv4Addr::new(10, 1, 2, 3));
+    /// assert_eq!(matches.next(), Some((default, 0, &"foo")));
+    /// assert_eq!(matches.next(), Some((less_specific, 8, &"bar")));
+    /// assert_eq!(matches.next(), Some((more_specific, 24, &"baz")));
+    /// assert_eq!(matches.next(), None);
+    /// ```
+    pub fn matches(&self, ip: A) -> Matches<'_, A, T> {
+        Matches {
+            inner: self.inner.matches_unchecked(ip.nibbles().as_ref()),
+            ip,
+        }
+    }
+
+    /// Mutable version of matches().
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use treebitmap::IpLookupTable;
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let mut table = IpLookupTable::new();
+    /// let less_specific = Ipv4Addr::new(10, 0, 0, 0);
+    /// let more_specific = Ipv4Addr::new(10, 1, 2, 0);
+    /// table.insert(less_specific, 8, 1);
+    /// table.insert(more_specific, 24, 2);
+    ///
+    /// for (_ip, _mask, val) in table.matches_mut(Ipv4Addr::new(10, 1, 2, 3)) {
+    ///     *val += 10;
+    /// }
+    ///
+    /// assert_eq!(table.exact_match(less_specific, 8), Some(&11));
+    /// assert_eq!(table.exact_match(more_specific, 24), Some(&12));
+    /// ```
+    pub fn matches_mut(&mut self, ip: A) -> MatchesMut<'_, A, T> {
+        MatchesMut {
+            inner: self.inner.matches_mut_unchecked(ip.nibbles().as_ref()),
+            ip,
+        }
+    }
+}
+
+impl<A, T> IpLookupTable<A, T>
+where
+    A: Address,
+    T: Clone,
+{
+    /// Insert a value for the address range from `start` to `end` inclusive.
+    /// The range is decomposed into the minimal set of prefixes covering it,
+    /// and a copy of the value is inserted for each. Returns the prefixes that
+    /// existed previously and their old values, in address order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use treebitmap::IpLookupTable;
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let mut table = IpLookupTable::new();
+    /// table.insert(Ipv4Addr::new(10, 0, 0, 24), 29, "foo");
+    /// table.insert(Ipv4Addr::new(10, 0, 0, 32), 27, "bar");
+    /// table.insert(Ipv4Addr::new(10, 0, 0, 64), 26, "quux");
+    ///
+    /// let start = Ipv4Addr::new(10, 0, 0, 17);
+    /// let end = Ipv4Addr::new(10, 0, 0, 63);
+    /// let replaced = table.insert_range(start, end, "baz");
+    /// assert_eq!(replaced, vec![
+    ///     (Ipv4Addr::new(10, 0, 0, 24), 29, "foo"),
+    ///     (Ipv4Addr::new(10, 0, 0, 32), 27, "bar"),
+    /// ]);
+    ///
+    /// let prefixes: Vec<_> = table.iter().map(|(ip, masklen, _)| (ip, masklen)).collect();
+    /// assert_eq!(prefixes, vec![
+    ///     (Ipv4Addr::new(10, 0, 0, 17), 32),
+    ///     (Ipv4Addr::new(10, 0, 0, 18), 31),
+    ///     (Ipv4Addr::new(10, 0, 0, 20), 30),
+    ///     (Ipv4Addr::new(10, 0, 0, 24), 29),
+    ///     (Ipv4Addr::new(10, 0, 0, 32), 27),
+    ///     (Ipv4Addr::new(10, 0, 0, 64), 26),
+    /// ]);
+    /// ```
+    pub fn insert_range(&mut self, start: A, end: A, value: T) -> Vec<(A, u32, T)> {
+        range_prefixes(start, end)
+            .into_iter()
+            .filter_map(|(ip, masklen)| {
+                self.insert(ip, masklen, value.clone())
+                    .map(|v| (ip, masklen, v))
+            })
+            .collect()
+    }
+}
+
+/// Panics if ```masklen``` exceeds the bits of the address.
+fn check_masklen<A: Address>(ip: A, masklen: u32) {
+    let bits = ip.nibbles().as_ref().len() * 4;
+    assert!(
+        masklen as usize <= bits,
+        "masklen {} exceeds the {} bits of the address",
+        masklen,
+        bits
+    );
+}
+
+/// Checks that ```masklen``` does not exceed the bits of the address, and that
+/// ```ip``` has no bits set to the right of mask.
+fn check_prefix<A: Address>(ip: A, masklen: u32) -> Result<(), TableError> {
+    let nibbles = ip.nibbles();
+    if masklen as usize > nibbles.as_ref().len() * 4 {
+        return Err(TableError::MasklenOutOfRange);
+    }
+    if ip.mask(masklen).nibbles().as_ref() != nibbles.as_ref() {
+        return Err(TableError::HostBitsSet);
+    }
+    Ok(())
+}
+
+/// Returns the minimal set of prefixes covering the address range from
+/// ```start``` to ```end``` inclusive, in address order. Each prefix is the
+/// largest one aligned at the first address not yet covered, which does not
+/// extend past ```end```.
+fn range_prefixes<A: Address>(start: A, end: A) -> Vec<(A, u32)> {
+    let end = end.nibbles();
+    let end = end.as_ref();
+    let mut ret = Vec::new();
+    let mut cur = start.nibbles().as_ref().to_vec();
+    let bits = cur.len() as u32 * 4;
+    while cur[..] <= *end {
+        let ip: A = Address::from_nibbles(&cur);
+        let masklen = (0..bits)
+            .find(|&masklen| {
+                ip.mask(masklen).nibbles().as_ref() == &cur[..]
+                    && last_address(&cur, masklen)[..] <= *end
+            })
+            .unwrap_or(bits);
+        ret.push((ip, masklen));
+
+        // continue after the last address of the prefix
+        cur = last_address(&cur, masklen);
+        match cur.iter().rposition(|&nibble| nibble < 0xf) {
+            Some(pos) => {
+                cur[pos] += 1;
+                for nibble in &mut cur[pos + 1..] {
+                    *nibble = 0;
+                }
+            }
+            None => break,
+        }
+    }
+    ret
+}
+
+/// Returns ```nibbles``` with the bits to the right of mask set.
+fn last_address(nibbles: &[u8], masklen: u32) -> Vec<u8> {
+    nibbles
+        .iter()
+        .enumerate()
+        .map(|(i, &nibble)| {
+            let bits = cmp::min(masklen.saturating_sub(i as u32 * 4), 4);
+            nibble | (0xf >> bits)
+        })
+        .collect()
+}
+
+impl<A, T> Default for IpLookupTable<A, T>
+where
+    A: Address,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<A, T> Clone for IpLookupTable<A, T>
+where
+    A: Address,
+    T: Clone,
+{
+    fn clone(&self) -> Self {
+        IpLookupTable {
+            inner: self.inner.clone(),
+            _addrtype: PhantomData,
+        }
+    }
+}
+
+/// Tables are equal if they contain the same prefixes with equal values,
+/// regardless of the order in which they were inserted or removed.
+impl<A, T> PartialEq for IpLookupTable<A, T>
+where
+    A: Address,
+    T: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len()
+            && self.iter().zip(other.iter()).all(|(a, b)| {
+                a.1 == b.1
+                    && a.0.mask(a.1).nibbles().as_ref() == b.0.mask(b.1).nibbles().as_ref()
+                    && a.2 == b.2
+            })
+    }
+}
+
+impl<A, T> Eq for IpLookupTable<A, T>
+where
+    A: Address,
+    T: Eq,
+{
+}
+
+impl<A, T> Hash for IpLookupTable<A, T>
+where
+    A: Address,
+    T: Hash,
+{
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.len().hash(state);
+        for (ip, masklen, value) in self.iter() {
+            ip.mask(masklen).nibbles().as_ref().hash(state);
+            masklen.hash(state);
+            value.hash(state);
+        }
+    }
+}
+
+/// Formats the table as a map from prefixes to values, in address order.
+impl<A, T> fmt::Debug for IpLookupTable<A, T>
+where
+    A: Address + fmt::Debug,
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut map = f.debug_map();
+        for (ip, masklen, value) in self.iter() {
+            map.entry(&format_args!("{:?}/{}", ip, masklen), value);
+        }
+        map.finish()
+    }
+}
+
+impl<A, T> Index<Prefix<A>> for IpLookupTable<A, T>
+where
+    A: Address,
+{
+    type Output = T;
+
+    /// Returns a reference to the value stored for the prefix.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the prefix is not present in the table.
+    fn index(&self, prefix: Prefix<A>) -> &T {
+        &self[(prefix.network(), prefix.len())]
+    }
+}
+
+impl<A, T> Index<(A, u32)> for IpLookupTable<A, T>
+where
+    A: Address,
+{
+    type Output = T;
+
+    /// Returns a reference to the value of the prefix designated by ip and
+    /// masklen.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the prefix is not present in the table.
+    fn index(&self, (ip, masklen): (A, u32)) -> &T {
+        self.exact_match(ip, masklen)
+            .expect("prefix not present in table")
+    }
+}
+
+/// A view into a single entry of an ```IpLookupTable```, which may either be
+/// vacant or occupied.
+///
+/// This enum is constructed from the ```entry``` method on ```IpLookupTable```.
+pub enum Entry<'a, A, T: 'a> {
+    /// An occupied entry.
+    Occupied(OccupiedEntry<'a, A, T>),
+    /// A vacant entry.
+    Vacant(VacantEntry<'a, A, T>),
+}
+
+/// A view into an occupied entry of an ```IpLookupTable```.
+pub struct OccupiedEntry<'a, A, T: 'a> {
+    inner: tree_bitmap::OccupiedEntry<'a, T>,
+    ip: A,
+    masklen: u32,
+}
+
+/// A view into a vacant entry of an ```IpLookupTable```.
+pub struct VacantEntry<'a, A, T: 'a> {
+    inner: tree_bitmap::VacantEntry<'a, T>,
+    ip: A,
+    masklen: u32,
+}
+
+impl<'a, A, T: 'a> Entry<'a, A, T>
+where
+    A: Address,
+{
+    /// Ensures a value is in the entry by inserting the default if empty, and
+    /// returns a mutable reference to the value in the entry.
+    pub fn or_insert(self, default: T) -> &'a mut T {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default),
+        }
+    }
+
+    /// Ensures a value is in the entry by inserting the result of the default
+    /// function if empty, and returns a mutable reference to the value in the
+    /// entry.
+    pub fn or_insert_with<F: FnOnce() -> T>(self, default: F) -> &'a mut T {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    /// Ensures a value is in the entry by inserting the default value if empty,
+    /// and returns a mutable reference to the value in the entry.
+    pub fn or_default(self) -> &'a mut T
+    where
+        T: Default,
+    {
+        self.or_insert_with(T::default)
+    }
+
+    /// Provides in-place mutable access to an occupied entry before any
+    /// potential inserts into the table.
+    pub fn and_modify<F: FnOnce(&mut T)>(self, f: F) -> Self {
+        match self {
+            Entry::Occupied(mut entry) => {
+                f(entry.get_mut());
+                Entry::Occupied(entry)
+            }
+            Entry::Vacant(entry) => Entry::Vacant(entry),
+        }
+    }
+
+    /// Returns the prefix of this entry, designated by ip and masklen.
+    pub fn key(&self) -> (A, u32) {
+        match *self {
+            Entry::Occupied(ref entry) => entry.key(),
+            Entry::Vacant(ref entry) => entry.key(),
+        }
+    }
 }
 
-impl<A, T> Default for IpLookupTable<A, T>
+impl<'a, A, T: 'a> OccupiedEntry<'a, A, T>
 where
     A: Address,
 {
-    fn default() -> Self {
-        Self::new()
+    /// Returns the prefix of this entry, designated by ip and masklen.
+    pub fn key(&self) -> (A, u32) {
+        (self.ip, self.masklen)
+    }
+
+    /// Gets a reference to the value in the entry.
+    pub fn get(&self) -> &T {
+        self.inner.get()
+    }
+
+    /// Gets a mutable reference to the value in the entry.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.inner.get_mut()
+    }
+
+    /// Converts the entry into a mutable reference to its value, bound to the
+    /// lifetime of the table.
+    pub fn into_mut(self) -> &'a mut T {
+        self.inner.into_mut()
+    }
+
+    /// Sets the value of the entry, and returns the entry's old value.
+    pub fn insert(&mut self, value: T) -> T {
+        self.inner.insert(value)
+    }
+
+    /// Takes the value of the entry out of the table, and returns it.
+    pub fn remove(self) -> T {
+        self.inner.remove()
+    }
+}
+
+impl<'a, A, T: 'a> VacantEntry<'a, A, T>
+where
+    A: Address,
+{
+    /// Returns the prefix of this entry, designated by ip and masklen.
+    pub fn key(&self) -> (A, u32) {
+        (self.ip, self.masklen)
+    }
+
+    /// Sets the value of the entry, and returns a mutable reference to it.
+    pub fn insert(self, value: T) -> &'a mut T {
+        self.inner.insert(value)
+    }
+}
+
+impl<'a, A, T: 'a> Iter<'a, A, T>
+where
+    A: Address,
+{
+    /// Returns a cursor positioned after the last prefix returned by
+    /// ```next```, which can be stored and passed to
+    /// ```IpLookupTable::iter_from_cursor``` to resume iteration later.
+    ///
+    /// The cursor only tracks forward iteration: ```next_back``` leaves it in
+    /// place, so prefixes taken from the back are returned again on resuming.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use treebitmap::{IpLookupTable, IterCursor};
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let mut table = IpLookupTable::new();
+    /// table.insert(Ipv4Addr::new(10, 0, 0, 0), 8, 1);
+    /// table.insert(Ipv4Addr::new(10, 1, 0, 0), 16, 2);
+    ///
+    /// let mut iter = table.iter();
+    /// iter.next();
+    /// iter.next_back();
+    /// let cursor = iter.cursor();
+    /// assert_eq!(cursor, IterCursor::after(Ipv4Addr::new(10, 0, 0, 0), 8));
+    /// ```
+    pub fn cursor(&self) -> IterCursor<A> {
+        self.cursor
+    }
+}
+
+impl<'a, A, T: 'a> IterMut<'a, A, T>
+where
+    A: Address,
+{
+    /// Returns a cursor positioned after the last prefix returned by
+    /// ```next```, which can be stored and passed to
+    /// ```IpLookupTable::iter_from_cursor``` to resume iteration later.
+    ///
+    /// The cursor only tracks forward iteration: ```next_back``` leaves it in
+    /// place, so prefixes taken from the back are returned again on resuming.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use treebitmap::{IpLookupTable, IterCursor};
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let mut table = IpLookupTable::new();
+    /// table.insert(Ipv4Addr::new(10, 0, 0, 0), 8, 1);
+    /// table.insert(Ipv4Addr::new(10, 1, 0, 0), 16, 2);
+    ///
+    /// let mut iter = table.iter_mut();
+    /// if let Some((_, _, value)) = iter.next() {
+    ///     *value += 10;
+    /// }
+    /// let cursor = iter.cursor();
+    /// assert_eq!(cursor, IterCursor::after(Ipv4Addr::new(10, 0, 0, 0), 8));
+    /// ```
+    pub fn cursor(&self) -> IterCursor<A> {
+        self.cursor
     }
 }
 
@@ -253,6 +1783,26 @@ where
 
     fn next(&mut self) -> Option<Self::Item> {
         match self.inner.next() {
+            Some((nibbles, masklen, value)) => {
+                let ip = Address::from_nibbles(&nibbles[..]);
+                self.cursor = IterCursor::after(ip, masklen);
+                Some((ip, masklen, value))
+            }
+            None => None,
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, A, T: 'a> DoubleEndedIterator for Iter<'a, A, T>
+where
+    A: Address,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        match self.inner.next_back() {
             Some((nibbles, masklen, value)) => {
                 Some((Address::from_nibbles(&nibbles[..]), masklen, value))
             }
@@ -261,6 +1811,8 @@ where
     }
 }
 
+impl<'a, A, T: 'a> ExactSizeIterator for Iter<'a, A, T> where A: Address {}
+
 impl<'a, A, T: 'a> Iterator for IterMut<'a, A, T>
 where
     A: Address,
@@ -269,6 +1821,26 @@ where
 
     fn next(&mut self) -> Option<Self::Item> {
         match self.inner.next() {
+            Some((nibbles, masklen, value)) => {
+                let ip = Address::from_nibbles(&nibbles[..]);
+                self.cursor = IterCursor::after(ip, masklen);
+                Some((ip, masklen, value))
+            }
+            None => None,
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, A, T: 'a> DoubleEndedIterator for IterMut<'a, A, T>
+where
+    A: Address,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        match self.inner.next_back() {
             Some((nibbles, masklen, value)) => {
                 Some((Address::from_nibbles(&nibbles[..]), masklen, value))
             }
@@ -277,11 +1849,91 @@ where
     }
 }
 
-impl<'a, A, T: 'a> Iterator for IntoIter<A, T>
+impl<'a, A, T: 'a> ExactSizeIterator for IterMut<'a, A, T> where A: Address {}
+
+impl<'a, A, T: 'a> Range<'a, A, T>
 where
     A: Address,
 {
-    type Item = (A, u32, T);
+    /// Returns a cursor positioned after the last prefix returned by the
+    /// iterator, which can be stored and passed to
+    /// ```IpLookupTable::iter_from_cursor``` to resume iteration later.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use treebitmap::{IpLookupTable, IterCursor};
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let mut table = IpLookupTable::new();
+    /// table.insert(Ipv4Addr::new(10, 0, 0, 0), 8, 1);
+    /// table.insert(Ipv4Addr::new(10, 1, 0, 0), 16, 2);
+    ///
+    /// let mut iter = table.iter_from(Ipv4Addr::new(10, 0, 0, 0), 8);
+    /// assert_eq!(iter.cursor(), IterCursor::at(Ipv4Addr::new(10, 0, 0, 0), 8));
+    /// iter.next();
+    /// assert_eq!(iter.cursor(), IterCursor::after(Ipv4Addr::new(10, 0, 0, 0), 8));
+    /// ```
+    pub fn cursor(&self) -> IterCursor<A> {
+        self.cursor
+    }
+}
+
+impl<'a, A, T: 'a> Iterator for Range<'a, A, T>
+where
+    A: Address,
+{
+    type Item = (A, u32, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.inner.next() {
+            Some((nibbles, masklen, value)) => {
+                let ip = Address::from_nibbles(&nibbles[..]);
+                self.cursor = IterCursor::after(ip, masklen);
+                Some((ip, masklen, value))
+            }
+            None => None,
+        }
+    }
+}
+
+impl<'a, A, T: 'a> Iterator for RangeMut<'a, A, T>
+where
+    A: Address,
+{
+    type Item = (A, u32, &'a mut T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.inner.next() {
+            Some((nibbles, masklen, value)) => {
+                Some((Address::from_nibbles(&nibbles[..]), masklen, value))
+            }
+            None => None,
+        }
+    }
+}
+
+impl<'a, A, T: 'a> Iterator for Children<'a, A, T>
+where
+    A: Address,
+{
+    type Item = (A, u32, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.inner.next() {
+            Some((nibbles, masklen, value)) => {
+                Some((Address::from_nibbles(&nibbles[..]), masklen, value))
+            }
+            None => None,
+        }
+    }
+}
+
+impl<'a, A, T: 'a> Iterator for Overlapping<'a, A, T>
+where
+    A: Address,
+{
+    type Item = (A, u32, &'a T);
 
     fn next(&mut self) -> Option<Self::Item> {
         match self.inner.next() {
@@ -293,6 +1945,81 @@ where
     }
 }
 
+impl<'a, A, T: 'a> Iterator for Matches<'a, A, T>
+where
+    A: Address,
+{
+    type Item = (A, u32, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.inner.next() {
+            Some((masklen, value)) => Some((self.ip.mask(masklen), masklen, value)),
+            None => None,
+        }
+    }
+}
+
+impl<'a, A, T: 'a> Iterator for MatchesMut<'a, A, T>
+where
+    A: Address,
+{
+    type Item = (A, u32, &'a mut T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.inner.next() {
+            Some((masklen, value)) => Some((self.ip.mask(masklen), masklen, value)),
+            None => None,
+        }
+    }
+}
+
+impl<A, T> Iterator for Drain<A, T>
+where
+    A: Address,
+{
+    type Item = (A, u32, T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner
+            .next()
+            .map(|(nibbles, masklen, value)| (Address::from_nibbles(&nibbles[..]), masklen, value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<A, T> Iterator for IntoIter<A, T>
+where
+    A: Address,
+{
+    type Item = (A, u32, T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner
+            .next()
+            .map(|(nibbles, masklen, value)| (Address::from_nibbles(&nibbles[..]), masklen, value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<A, T> DoubleEndedIterator for IntoIter<A, T>
+where
+    A: Address,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner
+            .next_back()
+            .map(|(nibbles, masklen, value)| (Address::from_nibbles(&nibbles[..]), masklen, value))
+    }
+}
+
+impl<A, T> ExactSizeIterator for IntoIter<A, T> where A: Address {}
+
 impl<A, T> IntoIterator for IpLookupTable<A, T>
 where
     A: Address,
@@ -309,25 +2036,139 @@ where
 }
 
 /// Iterator over prefixes and associated values. The prefixes are returned in
-/// "tree"-order.
+/// address order, less specific prefixes before more specific ones.
 #[doc(hidden)]
 pub struct Iter<'a, A, T: 'a> {
     inner: tree_bitmap::Iter<'a, T>,
+    cursor: IterCursor<A>,
+}
+
+/// Iterator over prefixes and associated values, starting at a given prefix
+/// or limited to the more specifics of a prefix. The prefixes are returned in
+/// address order, less specific prefixes before more specific ones.
+#[doc(hidden)]
+pub struct Range<'a, A, T: 'a> {
+    inner: tree_bitmap::Range<'a, T>,
+    cursor: IterCursor<A>,
+}
+
+/// Mutable iterator over the more specifics of a prefix and their associated
+/// values. The prefixes are returned in address order, less specific prefixes
+/// before more specific ones.
+#[doc(hidden)]
+pub struct RangeMut<'a, A, T: 'a> {
+    inner: tree_bitmap::RangeMut<'a, T>,
     _addrtype: PhantomData<A>,
 }
 
+/// Position of an iterator over an ```IpLookupTable```, in address order.
+///
+/// A cursor only records a prefix, so it is independent of the table and can be
+/// stored, for example to page through a table, and used to resume iteration
+/// with ```IpLookupTable::iter_from_cursor``` after the table was modified.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct IterCursor<A> {
+    ip: A,
+    masklen: u32,
+    inclusive: bool,
+}
+
+impl<A> IterCursor<A>
+where
+    A: Address,
+{
+    /// Returns a cursor positioned at the prefix designated by ip and
+    /// masklen, so that the prefix is the first one returned when resuming.
+    /// Bits to the right of mask are ignored.
+    pub fn at(ip: A, masklen: u32) -> Self {
+        IterCursor {
+            ip: ip.mask(masklen),
+            masklen,
+            inclusive: true,
+        }
+    }
+
+    /// Returns a cursor positioned after the prefix designated by ip and
+    /// masklen, so that resuming starts with the prefix following it. Bits to
+    /// the right of mask are ignored.
+    pub fn after(ip: A, masklen: u32) -> Self {
+        IterCursor {
+            ip: ip.mask(masklen),
+            masklen,
+            inclusive: false,
+        }
+    }
+
+    /// Returns the address of the prefix the cursor is positioned at or after.
+    pub fn ip(&self) -> A {
+        self.ip
+    }
+
+    /// Returns the mask length of the prefix the cursor is positioned at or
+    /// after.
+    pub fn masklen(&self) -> u32 {
+        self.masklen
+    }
+
+    /// Returns true if the prefix itself is returned when resuming, i.e. if
+    /// the cursor was created with ```at```.
+    pub fn is_inclusive(&self) -> bool {
+        self.inclusive
+    }
+}
+
 /// Mutable iterator over prefixes and associated values. The prefixes are
-/// returned in "tree"-order.
+/// returned in address order, less specific prefixes before more specific ones.
 #[doc(hidden)]
 pub struct IterMut<'a, A, T: 'a> {
     inner: tree_bitmap::IterMut<'a, T>,
-    _addrtype: PhantomData<A>,
+    cursor: IterCursor<A>,
 }
 
 /// Converts ```IpLookupTable``` into an iterator. The prefixes are returned in
-/// "tree"-order.
+/// address order, less specific prefixes before more specific ones.
 #[doc(hidden)]
 pub struct IntoIter<A, T> {
     inner: tree_bitmap::IntoIter<T>,
     _addrtype: PhantomData<A>,
 }
+
+/// Iterator over the immediate more specific prefixes of a prefix and their
+/// associated values. The prefixes are returned in address order.
+#[doc(hidden)]
+pub struct Children<'a, A, T: 'a> {
+    inner: tree_bitmap::Children<'a, T>,
+    _addrtype: PhantomData<A>,
+}
+
+/// Iterator over prefixes intersecting an address range and their associated
+/// values. The prefixes are returned in address order.
+#[doc(hidden)]
+pub struct Overlapping<'a, A, T: 'a> {
+    inner: tree_bitmap::Overlapping<'a, T>,
+    _addrtype: PhantomData<A>,
+}
+
+/// Iterator over prefixes matching an address and their associated values. The
+/// prefixes are returned from least to most specific.
+#[doc(hidden)]
+pub struct Matches<'a, A, T: 'a> {
+    inner: tree_bitmap::Matches<'a, T>,
+    ip: A,
+}
+
+/// Mutable iterator over prefixes matching an address and their associated
+/// values. The prefixes are returned from least to most specific.
+#[doc(hidden)]
+pub struct MatchesMut<'a, A, T: 'a> {
+    inner: tree_bitmap::MatchesMut<'a, T>,
+    ip: A,
+}
+
+/// Iterator over the prefixes and values removed by ```drain_prefix```. The
+/// prefixes are returned in address order.
+#[doc(hidden)]
+pub struct Drain<A, T> {
+    inner: tree_bitmap::Drain<T>,
+    _addrtype: PhantomData<A>,
+}
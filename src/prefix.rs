@@ -0,0 +1,284 @@
+// Copyright 2016 Hroi Sigurdsson
+//
+// Licensed under the MIT license <LICENSE-MIT or http://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed except according to those terms.
+
+use std::cmp::Ordering;
+#[cfg(not(feature = "alloc"))]
+use std::error::Error;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+
+use address::Address;
+
+/// An IP prefix, designated by an address and a mask length.
+///
+/// The address may have bits set to the right of the mask. ```addr``` and
+/// ```Display``` keep them as given, while ```network``` returns the address
+/// with them cleared. Comparison, ordering and hashing use the network
+/// address, so ```10.1.2.3/8``` equals ```10.0.0.0/8```. Prefixes are ordered by
+/// network address, then by mask length.
+///
+/// ```IpLookupTable``` takes a ```Prefix``` in ```insert_prefix```,
+/// ```remove_prefix```, ```get```, ```get_mut``` and indexing, and returns one
+/// from ```lookup``` and ```PrefixIterator::prefixes```. Its other methods take
+/// an address and mask length, which can be passed as ```prefix.network()```
+/// and ```prefix.len()```.
+///
+/// # Examples
+///
+/// ```
+/// use treebitmap::Prefix;
+/// use std::net::{Ipv4Addr, Ipv6Addr};
+///
+/// let prefix: Prefix<Ipv4Addr> = "10.0.0.0/8".parse().unwrap();
+/// assert_eq!(prefix, Prefix::new(Ipv4Addr::new(10, 0, 0, 0), 8));
+/// assert_eq!(prefix.to_string(), "10.0.0.0/8");
+///
+/// let prefix: Prefix<Ipv6Addr> = "2001:db8::1/32".parse().unwrap();
+/// assert_eq!(prefix.network(), Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0));
+/// assert_eq!(prefix.len(), 32);
+/// assert_eq!(prefix, "2001:db8::/32".parse().unwrap());
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct Prefix<A> {
+    addr: A,
+    len: u32,
+}
+
+impl<A> Prefix<A>
+where
+    A: Address,
+{
+    /// Returns the prefix designated by addr and len.
+    ///
+    /// # Panics
+    ///
+    /// Panics if len exceeds the number of bits in the address.
+    pub fn new(addr: A, len: u32) -> Self {
+        assert!(
+            len <= max_len::<A>(),
+            "prefix length {} exceeds the {} bits of the address",
+            len,
+            max_len::<A>()
+        );
+        Prefix { addr, len }
+    }
+
+    /// Returns the address of the prefix, as given.
+    pub fn addr(&self) -> A {
+        self.addr
+    }
+
+    /// Returns the mask length of the prefix.
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> u32 {
+        self.len
+    }
+
+    /// Returns the network address of the prefix, which is the address with
+    /// the bits to the right of mask cleared.
+    pub fn network(&self) -> A {
+        self.addr.mask(self.len)
+    }
+
+    /// Returns true if ```addr``` is within the prefix.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use treebitmap::Prefix;
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let prefix = Prefix::new(Ipv4Addr::new(10, 0, 0, 0), 8);
+    /// assert!(prefix.contains(Ipv4Addr::new(10, 1, 2, 3)));
+    /// assert!(!prefix.contains(Ipv4Addr::new(11, 1, 2, 3)));
+    /// ```
+    pub fn contains(&self, addr: A) -> bool {
+        addr.mask(self.len).nibbles().as_ref() == self.network().nibbles().as_ref()
+    }
+
+    /// Returns true if ```other``` is equal to or more specific than this
+    /// prefix.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use treebitmap::Prefix;
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let prefix = Prefix::new(Ipv4Addr::new(10, 0, 0, 0), 8);
+    /// assert!(prefix.contains_prefix(Prefix::new(Ipv4Addr::new(10, 1, 0, 0), 16)));
+    /// assert!(prefix.contains_prefix(prefix));
+    /// assert!(!prefix.contains_prefix(Prefix::new(Ipv4Addr::new(0, 0, 0, 0), 0)));
+    /// ```
+    pub fn contains_prefix(&self, other: Prefix<A>) -> bool {
+        other.len >= self.len && self.contains(other.addr)
+    }
+}
+
+impl<A> PartialEq for Prefix<A>
+where
+    A: Address + PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.network() == other.network()
+    }
+}
+
+impl<A> Eq for Prefix<A> where A: Address + Eq {}
+
+impl<A> PartialOrd for Prefix<A>
+where
+    A: Address + Ord,
+{
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<A> Ord for Prefix<A>
+where
+    A: Address + Ord,
+{
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.network(), self.len).cmp(&(other.network(), other.len))
+    }
+}
+
+impl<A> Hash for Prefix<A>
+where
+    A: Address + Hash,
+{
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.network().hash(state);
+        self.len.hash(state);
+    }
+}
+
+/// Returns the number of bits in addresses of type ```A```.
+fn max_len<A: Address>() -> u32 {
+    A::from_nibbles(&[]).nibbles().as_ref().len() as u32 * 4
+}
+
+impl<A> From<(A, u32)> for Prefix<A>
+where
+    A: Address,
+{
+    fn from((addr, len): (A, u32)) -> Self {
+        Prefix::new(addr, len)
+    }
+}
+
+impl<A> From<Prefix<A>> for (A, u32) {
+    fn from(prefix: Prefix<A>) -> Self {
+        (prefix.addr, prefix.len)
+    }
+}
+
+impl<A> fmt::Display for Prefix<A>
+where
+    A: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.len)
+    }
+}
+
+impl<A> FromStr for Prefix<A>
+where
+    A: Address + FromStr,
+{
+    type Err = PrefixParseError;
+
+    /// Parses a prefix in the form ```addr/len```, for example ```10.0.0.0/8```
+    /// or ```2001:db8::/32```.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, len) = s.split_once('/').ok_or(PrefixParseError(()))?;
+        let addr = addr.parse().map_err(|_| PrefixParseError(()))?;
+        if !len.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(PrefixParseError(()));
+        }
+        match len.parse() {
+            Ok(len) if len <= max_len::<A>() => Ok(Prefix { addr, len }),
+            _ => Err(PrefixParseError(())),
+        }
+    }
+}
+
+/// An error which can be returned when parsing a ```Prefix```.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PrefixParseError(());
+
+impl fmt::Display for PrefixParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("invalid IP prefix syntax")
+    }
+}
+
+#[cfg(not(feature = "alloc"))]
+impl Error for PrefixParseError {}
+
+/// Extension of iterators over ```(ip, masklen, value)``` triples, such as
+/// those returned by ```IpLookupTable```, with an adapter returning
+/// ```Prefix``` and value pairs.
+///
+/// # Examples
+///
+/// ```
+/// use treebitmap::{IpLookupTable, Prefix, PrefixIterator};
+/// use std::net::Ipv4Addr;
+///
+/// let mut table = IpLookupTable::new();
+/// let prefix: Prefix<Ipv4Addr> = "10.0.0.0/8".parse().unwrap();
+/// table.insert_prefix(prefix, "foo");
+///
+/// let mut iter = table.iter().prefixes();
+/// assert_eq!(iter.next(), Some((prefix, &"foo")));
+/// assert_eq!(iter.next(), None);
+/// ```
+pub trait PrefixIterator<A, V>: Iterator<Item = (A, u32, V)> + Sized {
+    /// Returns iterator over the prefixes and values of this iterator.
+    fn prefixes(self) -> Prefixes<Self> {
+        Prefixes { inner: self }
+    }
+}
+
+impl<A, V, I> PrefixIterator<A, V> for I where I: Iterator<Item = (A, u32, V)> {}
+
+/// Iterator adapter returning ```Prefix``` and value pairs, created by
+/// ```PrefixIterator::prefixes```.
+pub struct Prefixes<I> {
+    inner: I,
+}
+
+impl<A, V, I> Iterator for Prefixes<I>
+where
+    I: Iterator<Item = (A, u32, V)>,
+{
+    type Item = (Prefix<A>, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner
+            .next()
+            .map(|(addr, len, value)| (Prefix { addr, len }, value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<A, V, I> DoubleEndedIterator for Prefixes<I>
+where
+    I: DoubleEndedIterator<Item = (A, u32, V)>,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner
+            .next_back()
+            .map(|(addr, len, value)| (Prefix { addr, len }, value))
+    }
+}
+
+impl<A, V, I> ExactSizeIterator for Prefixes<I> where I: ExactSizeIterator<Item = (A, u32, V)> {}
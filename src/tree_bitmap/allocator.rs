@@ -3,9 +3,13 @@
 // Licensed under the MIT license <LICENSE-MIT or http://opensource.org/licenses/MIT>.
 // This file may not be copied, modified, or distributed except according to those terms.
 
+#[cfg(feature = "alloc")]
+use alloc::collections::TryReserveError;
 #[cfg(feature = "alloc")]
 use alloc::vec::Vec;
 use std::cmp;
+#[cfg(not(feature = "alloc"))]
+use std::collections::TryReserveError;
 use std::fmt;
 use std::mem;
 use std::ptr;
@@ -39,6 +43,19 @@ impl<T> RawVec<T> {
         self.mem = vec.as_mut_ptr();
         mem::forget(vec);
     }
+
+    pub fn try_reserve(
+        &mut self,
+        used_cap: usize,
+        extra_cap: usize,
+    ) -> Result<(), TryReserveError> {
+        let mut vec = unsafe { Vec::<T>::from_raw_parts(self.mem, used_cap, self.cap) };
+        let ret = vec.try_reserve(extra_cap);
+        self.cap = vec.capacity();
+        self.mem = vec.as_mut_ptr();
+        mem::forget(vec);
+        ret
+    }
 }
 
 impl<T> Drop for RawVec<T> {
@@ -114,6 +131,14 @@ impl<T: Sized> BucketVec<T> {
         }
     }
 
+    /// Reserve memory so that allocating and freeing ```slots``` more slots
+    /// does not allocate.
+    pub fn try_reserve(&mut self, slots: usize) -> Result<(), TryReserveError> {
+        self.buf
+            .try_reserve(self.len as usize, slots * self.spacing as usize)?;
+        self.freelist.try_reserve(slots)
+    }
+
     /// Free a bucket slot.
     pub fn free_slot(&mut self, slot: u32) {
         self.freelist.push(slot)
@@ -190,10 +215,7 @@ impl<T: Sized> BucketVec<T> {
                 (self.spacing - index - 1) as usize,
             );
             if cfg!(debug_assertions) {
-                ptr::write(
-                    dst_ptr.offset((self.spacing - index - 1) as isize),
-                    mem::zeroed(),
-                );
+                ptr::write_bytes(dst_ptr.offset((self.spacing - index - 1) as isize), 0, 1);
             }
         }
         ret
@@ -333,6 +355,16 @@ impl<T: Sized> Allocator<T> {
         }
     }
 
+    /// Reserve memory for allocating ```first``` more slots in the bucket of
+    /// the smallest collections, and ```rest``` more slots in each of the other
+    /// buckets.
+    pub fn try_reserve(&mut self, first: usize, rest: usize) -> Result<(), TryReserveError> {
+        for (i, buckvec) in self.buckets.iter_mut().enumerate() {
+            buckvec.try_reserve(if i == 0 { first } else { rest })?;
+        }
+        Ok(())
+    }
+
     pub fn free(&mut self, hdl: &mut AllocatorHandle) {
         debug_assert!(hdl.len == 0, "tried to free non-empty collection");
         let bucket_index = choose_bucket(hdl.len) as usize;
@@ -340,6 +372,15 @@ impl<T: Sized> Allocator<T> {
         hdl.offset = 0;
     }
 
+    /// Free a collection without dropping its contents. Any items still
+    /// stored in it must have been moved out beforehand.
+    pub fn release(&mut self, hdl: &mut AllocatorHandle) {
+        let bucket_index = choose_bucket(hdl.len) as usize;
+        self.buckets[bucket_index].free_slot(hdl.offset);
+        hdl.len = 0;
+        hdl.offset = 0;
+    }
+
     pub fn set(&mut self, hdl: &AllocatorHandle, index: u32, value: T) {
         let bucket_index = choose_bucket(hdl.len) as usize;
         self.buckets[bucket_index].set_slot_entry(hdl.offset, index, value)
@@ -455,7 +496,7 @@ mod tests {
             bucket.set_slot_entry(slot, i, 1000 + i);
         }
         for i in 0..spacing {
-            let mut x = bucket.get_slot_entry_mut(slot, i);
+            let x = bucket.get_slot_entry_mut(slot, i);
             *x += 1;
         }
         for i in 0..spacing {
@@ -542,7 +583,7 @@ mod tests {
         }
 
         for i in 0..32 {
-            let mut x = alloc.get_mut(&hdl, i);
+            let x = alloc.get_mut(&hdl, i);
             *x += 1;
         }
 
@@ -0,0 +1,183 @@
+// Copyright 2016 Hroi Sigurdsson
+//
+// Licensed under the MIT license <LICENSE-MIT or http://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed except according to those terms.
+
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+use super::node::{self, MatchResult, Node};
+use super::{NodeLocation, TreeBitmap, ADDRESS_ORDER, PREFIX_OF_BIT};
+
+/// Read-only cursor for walking the nodes of the trie.
+///
+/// Each node covers one nibble (4 bits) of the key. A node stores the results
+/// for prefixes ending within its nibble, and has up to 16 child nodes, one for
+/// each value of the nibble.
+pub struct TrieCursor<'a, T: 'a> {
+    trie: &'a TreeBitmap<T>,
+    node: Node,
+    location: NodeLocation,
+    ancestors: Vec<(Node, NodeLocation)>,
+    nibbles: Vec<u8>,
+}
+
+impl<'a, T: 'a> TrieCursor<'a, T> {
+    /// Returns a cursor positioned at the root node of ```trie```.
+    pub fn new(trie: &'a TreeBitmap<T>) -> Self {
+        TrieCursor {
+            trie,
+            node: *trie.trienodes.get(&trie.root_handle(), 0),
+            location: NodeLocation::ROOT,
+            ancestors: Vec::new(),
+            nibbles: Vec::new(),
+        }
+    }
+
+    /// Returns the number of nibbles between the root and the current node.
+    pub fn depth(&self) -> usize {
+        self.nibbles.len()
+    }
+
+    /// Returns the nibbles leading from the root to the current node.
+    pub fn nibbles(&self) -> &[u8] {
+        &self.nibbles
+    }
+
+    /// Returns the location of the current node in the trie.
+    pub(crate) fn location(&self) -> NodeLocation {
+        self.location
+    }
+
+    /// Returns true if the current node is an end node, which stores the
+    /// results of full nibbles itself instead of in child nodes.
+    pub fn is_endnode(&self) -> bool {
+        self.node.is_endnode()
+    }
+
+    /// Returns the number of results stored in the current node.
+    pub fn result_count(&self) -> u32 {
+        self.node.result_count()
+    }
+
+    /// Returns the number of children of the current node.
+    pub fn child_count(&self) -> u32 {
+        self.node.child_count()
+    }
+
+    /// Returns iterator over the results stored in the current node, in
+    /// address order.
+    pub fn results(&self) -> TrieResults<'a, T> {
+        TrieResults {
+            trie: self.trie,
+            node: self.node,
+            bits_searched: (self.nibbles.len() * 4) as u32,
+            pos: 0,
+        }
+    }
+
+    /// Returns iterator over the nibbles of the children of the current node,
+    /// in ascending order.
+    pub fn children(&self) -> TrieChildren {
+        TrieChildren {
+            external: self.node.external(),
+        }
+    }
+
+    /// Returns true if the current node has a child for ```nibble```.
+    pub fn has_child(&self, nibble: u8) -> bool {
+        nibble < 16 && self.node.external() & (1 << (15 - nibble)) > 0
+    }
+
+    /// Move to the child of the current node for ```nibble```. Returns false,
+    /// leaving the cursor in place, if there is no such child.
+    pub fn descend(&mut self, nibble: u8) -> bool {
+        if !self.has_child(nibble) {
+            return false;
+        }
+        match self.node.match_external(node::MATCH_MASKS[nibble as usize]) {
+            MatchResult::Chase(child_hdl, child_index) => {
+                let child_node = *self.trie.trienodes.get(&child_hdl, child_index);
+                self.ancestors.push((self.node, self.location));
+                self.nibbles.push(nibble);
+                self.node = child_node;
+                self.location = NodeLocation::new(&child_hdl, child_index);
+                true
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Move to the parent of the current node. Returns false if the cursor
+    /// is at the root.
+    pub fn ascend(&mut self) -> bool {
+        match self.ancestors.pop() {
+            Some((parent, location)) => {
+                self.nibbles.pop();
+                self.node = parent;
+                self.location = location;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl<'a, T: 'a> Clone for TrieCursor<'a, T> {
+    fn clone(&self) -> Self {
+        TrieCursor {
+            trie: self.trie,
+            node: self.node,
+            location: self.location,
+            ancestors: self.ancestors.clone(),
+            nibbles: self.nibbles.clone(),
+        }
+    }
+}
+
+/// Iterator over the results stored in a trie node. Each result is returned
+/// with the bits of its prefix within the node, left aligned in a nibble, and
+/// its full mask length.
+pub struct TrieResults<'a, T: 'a> {
+    trie: &'a TreeBitmap<T>,
+    node: Node,
+    bits_searched: u32,
+    pos: usize,
+}
+
+impl<'a, T: 'a> Iterator for TrieResults<'a, T> {
+    type Item = (u8, u32, &'a T); //(nibble, masklen, &T)
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pos < ADDRESS_ORDER.len() {
+            let bit_index = ADDRESS_ORDER[self.pos] as usize;
+            self.pos += 1;
+            if let MatchResult::Match(result_hdl, result_index, _) =
+                self.node.match_internal(1 << (31 - bit_index))
+            {
+                let value = self.trie.results.get(&result_hdl, result_index);
+                let masklen = self.bits_searched + node::BIT_MATCH[bit_index];
+                return Some((PREFIX_OF_BIT[bit_index], masklen, value));
+            }
+        }
+        None
+    }
+}
+
+/// Iterator over the nibbles of the children of a trie node.
+pub struct TrieChildren {
+    external: u32,
+}
+
+impl Iterator for TrieChildren {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        if self.external == 0 {
+            return None;
+        }
+        let nibble = self.external.leading_zeros() - 16;
+        self.external &= !(1 << (15 - nibble));
+        Some(nibble as u8)
+    }
+}
@@ -3,29 +3,71 @@
 // Licensed under the MIT license <LICENSE-MIT or http://opensource.org/licenses/MIT>.
 // This file may not be copied, modified, or distributed except according to those terms.
 
+//! Tree-bitmap trie keyed by bit strings.
+//!
+//! Keys are given as a slice of nibbles (values 0 to 15), most significant
+//! nibble first, and the number of leading bits of the key that are
+//! significant. Bits to the right of the key length are ignored by lookups.
+//!
+//! All methods panic if the key length exceeds the bits held by the nibbles,
+//! or if the nibbles contain values above 15. Methods naming an exact key,
+//! such as ```insert```, ```exact_match``` and ```remove```, also panic if the
+//! key has bits set to the right of the key length.
+
+#[cfg(feature = "alloc")]
+use alloc::collections::TryReserveError;
 #[cfg(feature = "alloc")]
 use alloc::vec;
 #[cfg(feature = "alloc")]
 use alloc::vec::Vec;
 use std::cmp;
+#[cfg(not(feature = "alloc"))]
+use std::collections::TryReserveError;
+use std::hash::{Hash, Hasher};
+#[cfg(not(feature = "alloc"))]
+use std::vec;
 
 mod allocator;
+mod cursor;
 mod node;
+mod node_data;
 
 use self::allocator::{Allocator, AllocatorHandle};
+pub use self::cursor::{TrieChildren, TrieCursor, TrieResults};
 use self::node::{MatchResult, Node};
+use self::node_data::NodeOp;
+pub(crate) use self::node_data::{NodeData, NodeLocation};
 use std::ptr;
 
+/// Tree-bitmap trie mapping bit strings of any length to values.
+///
+/// # Examples
+///
+/// ```
+/// use treebitmap::tree_bitmap::TreeBitmap;
+///
+/// let mut tbm = TreeBitmap::new();
+/// // the 6 bit string 1010 01
+/// tbm.insert(&[0b1010, 0b0100], 6, "foo");
+/// // the 12 bit string 1010 0110 0001
+/// tbm.insert(&[0b1010, 0b0110, 0b0001], 12, "bar");
+///
+/// assert_eq!(tbm.exact_match(&[0b1010, 0b0100], 6), Some(&"foo"));
+/// assert_eq!(tbm.longest_match(&[0b1010, 0b0110, 0b0001, 0b1111]), Some((12, &"bar")));
+/// assert_eq!(tbm.longest_match(&[0b1010, 0b0111]), Some((6, &"foo")));
+/// assert_eq!(tbm.longest_match(&[0b1011]), None);
+/// ```
 // #[derive(Debug)]
 pub struct TreeBitmap<T: Sized> {
     trienodes: Allocator<Node>,
     results: Allocator<T>,
     len: usize,
-    should_drop: bool, // drop contents on drop?
+    should_drop: bool,             // drop contents on drop?
+    node_log: Option<Vec<NodeOp>>, // changes to the node layout, if recorded
 }
 
 impl<T: Sized> TreeBitmap<T> {
-    /// Returns ````TreeBitmap ```` with 0 start capacity.
+    /// Returns ```TreeBitmap``` with 0 start capacity.
     pub fn new() -> Self {
         Self::with_capacity(0)
     }
@@ -41,6 +83,7 @@ impl<T: Sized> TreeBitmap<T> {
             results: Allocator::with_capacity(n),
             len: 0,
             should_drop: true,
+            node_log: None,
         }
     }
 
@@ -70,7 +113,7 @@ impl<T: Sized> TreeBitmap<T> {
         let nodes_to_pushdown = (node.internal() & 0x0000_ffff).count_ones();
         if nodes_to_pushdown > 0 {
             let mut result_hdl = node.result_handle();
-            let mut child_node_hdl = self.trienodes.alloc(0);
+            let mut child_node_hdl = self.alloc_nodes();
 
             for _ in 0..nodes_to_pushdown {
                 // allocate space for child result value
@@ -84,8 +127,7 @@ impl<T: Sized> TreeBitmap<T> {
                 child_node.result_ptr = child_result_hdl.offset;
                 // append trienode to collection
                 let insert_node_at = child_node_hdl.len;
-                self.trienodes
-                    .insert(&mut child_node_hdl, insert_node_at, child_node);
+                self.insert_node(&mut child_node_hdl, insert_node_at, child_node);
             }
             // the result data may have moved to a smaller bucket, update the
             // result pointer
@@ -101,32 +143,243 @@ impl<T: Sized> TreeBitmap<T> {
         // note: we do not need to touch the external bits
     }
 
-    /// longest match lookup of ```nibbles```. Returns bits matched as u32, and reference to T.
+    /// Longest match lookup of ```nibbles```. Returns bits matched as u32, and reference to T.
     pub fn longest_match(&self, nibbles: &[u8]) -> Option<(u32, &T)> {
+        check_key(nibbles, 0);
+        self.longest_match_unchecked(nibbles)
+    }
+
+    pub(crate) fn longest_match_unchecked(&self, nibbles: &[u8]) -> Option<(u32, &T)> {
+        match self.find_longest(nibbles, 0, (nibbles.len() * 4) as u32) {
+            Some((bits_matched, result_hdl, result_index)) => {
+                Some((bits_matched, self.results.get(&result_hdl, result_index)))
+            }
+            None => None,
+        }
+    }
+
+    /// Longest match lookup of ```nibbles```, considering only values for
+    /// which ```f``` returns true. ```f``` is called while descending the trie,
+    /// so it may see less specific matches before more specific ones.
+    pub fn longest_match_where<F>(&self, nibbles: &[u8], f: F) -> Option<(u32, &T)>
+    where
+        F: FnMut(u32, &T) -> bool,
+    {
+        check_key(nibbles, 0);
+        self.longest_match_where_unchecked(nibbles, f)
+    }
+
+    pub(crate) fn longest_match_where_unchecked<F>(
+        &self,
+        nibbles: &[u8],
+        mut f: F,
+    ) -> Option<(u32, &T)>
+    where
+        F: FnMut(u32, &T) -> bool,
+    {
+        let (mut cur, mut pending) = self.first_match_node(nibbles);
+        let mut depth = 0;
+        let mut best = None;
+        while let Some(cur_node) = cur {
+            // try the node's matches from most to least specific, keeping the
+            // first accepted one as it beats those of the nodes above
+            while pending > 0 {
+                let bit_index = 31 - pending.trailing_zeros();
+                pending ^= node::MSB >> bit_index;
+                let (bits_matched, hdl, index) = match_location(&cur_node, depth, bit_index);
+                let value = self.results.get(&hdl, index);
+                if f(bits_matched, value) {
+                    best = Some((bits_matched, value));
+                    break;
+                }
+            }
+            cur = None;
+            if cur_node.is_endnode() || depth >= nibbles.len() {
+                break;
+            }
+            let match_mask = node::MATCH_MASKS[nibbles[depth] as usize];
+            if let MatchResult::Chase(child_hdl, child_index) = cur_node.match_external(match_mask)
+            {
+                let child_node = *self.trienodes.get(&child_hdl, child_index);
+                depth += 1;
+                pending = child_node.internal() & self::match_mask(nibbles, depth);
+                cur = Some(child_node);
+            }
+        }
+        best
+    }
+
+    /// Mutable version of ```longest_match```.
+    pub fn longest_match_mut(&mut self, nibbles: &[u8]) -> Option<(u32, &mut T)> {
+        check_key(nibbles, 0);
+        self.longest_match_mut_unchecked(nibbles)
+    }
+
+    pub(crate) fn longest_match_mut_unchecked(&mut self, nibbles: &[u8]) -> Option<(u32, &mut T)> {
+        match self.find_longest(nibbles, 0, (nibbles.len() * 4) as u32) {
+            Some((bits_matched, result_hdl, result_index)) => Some((
+                bits_matched,
+                self.results.get_mut(&result_hdl, result_index),
+            )),
+            None => None,
+        }
+    }
+
+    /// Shortest match lookup of ```nibbles```. Returns bits matched as u32,
+    /// and reference to T.
+    pub fn shortest_match(&self, nibbles: &[u8]) -> Option<(u32, &T)> {
+        check_key(nibbles, 0);
+        self.shortest_match_unchecked(nibbles)
+    }
+
+    pub(crate) fn shortest_match_unchecked(&self, nibbles: &[u8]) -> Option<(u32, &T)> {
+        let mut cur_hdl = self.root_handle();
+        let mut cur_index = 0;
+        let mut bits_searched = 0;
+        let mut bits_left = (nibbles.len() * 4) as u32;
+
+        loop {
+            let nibble = nibbles
+                .get(bits_searched as usize / 4)
+                .cloned()
+                .unwrap_or(0);
+            let match_mask = node::MATCH_MASKS[nibble as usize] & length_mask(bits_left);
+            let cur_node = *self.trienodes.get(&cur_hdl, cur_index);
+            let hits = cur_node.internal() & match_mask;
+
+            if hits > 0 {
+                // the most significant hit is the least specific prefix
+                let bit_index = hits.leading_zeros();
+                let result_index = match bit_index {
+                    0 => 0,
+                    _ => (cur_node.internal() >> (32 - bit_index)).count_ones(),
+                };
+                let bits_matched = bits_searched + node::BIT_MATCH[bit_index as usize];
+                let value = self.results.get(&cur_node.result_handle(), result_index);
+                return Some((bits_matched, value));
+            }
+
+            if cur_node.is_endnode() || bits_left < 4 {
+                break;
+            }
+            match cur_node.match_external(match_mask) {
+                MatchResult::Chase(child_hdl, child_index) => {
+                    bits_searched += 4;
+                    bits_left -= 4;
+                    cur_hdl = child_hdl;
+                    cur_index = child_index;
+                }
+                MatchResult::None => {
+                    break;
+                }
+                _ => unreachable!(),
+            }
+        }
+        None
+    }
+
+    /// Longest match lookup of the prefix ```nibbles```/```masklen```, only
+    /// considering prefixes of at most ```masklen``` bits.
+    pub fn longest_match_prefix(&self, nibbles: &[u8], masklen: u32) -> Option<(u32, &T)> {
+        check_key(nibbles, masklen);
+        self.longest_match_prefix_unchecked(nibbles, masklen)
+    }
+
+    pub(crate) fn longest_match_prefix_unchecked(
+        &self,
+        nibbles: &[u8],
+        masklen: u32,
+    ) -> Option<(u32, &T)> {
+        match self.find_longest(nibbles, 0, masklen) {
+            Some((bits_matched, result_hdl, result_index)) => {
+                Some((bits_matched, self.results.get(&result_hdl, result_index)))
+            }
+            None => None,
+        }
+    }
+
+    /// Longest match lookup of ```nibbles```, only considering prefixes of
+    /// ```min_len``` to ```max_len``` bits. ```max_len``` may exceed the bits
+    /// of ```nibbles```.
+    pub fn longest_match_within(
+        &self,
+        nibbles: &[u8],
+        min_len: u32,
+        max_len: u32,
+    ) -> Option<(u32, &T)> {
+        check_key(nibbles, 0);
+        self.longest_match_within_unchecked(nibbles, min_len, max_len)
+    }
+
+    pub(crate) fn longest_match_within_unchecked(
+        &self,
+        nibbles: &[u8],
+        min_len: u32,
+        max_len: u32,
+    ) -> Option<(u32, &T)> {
+        let max_len = cmp::min(max_len, (nibbles.len() * 4) as u32);
+        if min_len > max_len {
+            return None;
+        }
+        match self.find_longest(nibbles, min_len, max_len) {
+            Some((bits_matched, result_hdl, result_index)) => {
+                Some((bits_matched, self.results.get(&result_hdl, result_index)))
+            }
+            None => None,
+        }
+    }
+
+    /// Returns the longest prefix strictly covering ```nibbles```/```masklen```.
+    pub fn parent(&self, nibbles: &[u8], masklen: u32) -> Option<(u32, &T)> {
+        check_key(nibbles, masklen);
+        self.parent_unchecked(nibbles, masklen)
+    }
+
+    pub(crate) fn parent_unchecked(&self, nibbles: &[u8], masklen: u32) -> Option<(u32, &T)> {
+        match masklen {
+            0 => None,
+            _ => self.longest_match_prefix_unchecked(nibbles, masklen - 1),
+        }
+    }
+
+    /// Returns bits matched and the location of the value of the longest
+    /// match of ```nibbles``` no longer than ```masklen```.
+    fn find_longest(
+        &self,
+        nibbles: &[u8],
+        min_len: u32,
+        max_len: u32,
+    ) -> Option<(u32, AllocatorHandle, u32)> {
         let mut cur_hdl = self.root_handle();
         let mut cur_index = 0;
         let mut bits_matched = 0;
         let mut bits_searched = 0;
+        let mut bits_left = max_len;
         let mut best_match: Option<(AllocatorHandle, u32)> = None; // result handle + index
 
-        for nibble in nibbles {
+        loop {
+            let nibble = nibbles
+                .get(bits_searched as usize / 4)
+                .cloned()
+                .unwrap_or(0);
             let cur_node = *self.trienodes.get(&cur_hdl, cur_index);
-            let match_mask = node::MATCH_MASKS[*nibble as usize];
+            let match_mask = node::MATCH_MASKS[nibble as usize] & length_mask(bits_left);
 
-            if let MatchResult::Match(result_hdl, result_index, matching_bit_index) =
-                cur_node.match_internal(match_mask)
+            if let MatchResult::Match(result_hdl, result_index, matching_bit_index) = cur_node
+                .match_internal(match_mask & min_length_mask(min_len.saturating_sub(bits_searched)))
             {
                 bits_matched = bits_searched;
                 bits_matched += node::BIT_MATCH[matching_bit_index as usize];
                 best_match = Some((result_hdl, result_index));
             }
 
-            if cur_node.is_endnode() {
+            if cur_node.is_endnode() || bits_left < 4 {
                 break;
             }
             match cur_node.match_external(match_mask) {
                 MatchResult::Chase(child_hdl, child_index) => {
                     bits_searched += 4;
+                    bits_left -= 4;
                     cur_hdl = child_hdl;
                     cur_index = child_index;
                     continue;
@@ -138,15 +391,28 @@ impl<T: Sized> TreeBitmap<T> {
             }
         }
 
-        match best_match {
-            Some((result_hdl, result_index)) => {
-                Some((bits_matched, self.results.get(&result_hdl, result_index)))
-            }
-            None => None,
-        }
+        best_match.map(|(result_hdl, result_index)| (bits_matched, result_hdl, result_index))
     }
 
+    /// Insert a value for ```nibbles```/```masklen```. If the key existed
+    /// previously, the old value is returned.
     pub fn insert(&mut self, nibbles: &[u8], masklen: u32, value: T) -> Option<T> {
+        check_exact_key(nibbles, masklen);
+        self.insert_unchecked(nibbles, masklen, value)
+    }
+
+    pub(crate) fn insert_unchecked(&mut self, nibbles: &[u8], masklen: u32, value: T) -> Option<T> {
+        self.insert_result(nibbles, masklen, value).0
+    }
+
+    /// Insert ```value```, returning the replaced value if any, along with the
+    /// location the value was stored at.
+    fn insert_result(
+        &mut self,
+        nibbles: &[u8],
+        masklen: u32,
+        value: T,
+    ) -> (Option<T>, AllocatorHandle, u32) {
         let mut cur_hdl = self.root_handle();
         let mut cur_index = 0;
         let mut bits_left = masklen;
@@ -187,17 +453,19 @@ impl<T: Sized> TreeBitmap<T> {
                     >> (bitmap & node::END_BIT_MASK).trailing_zeros())
                 .count_ones();
 
-                if cur_node.internal() & (bitmap & node::END_BIT_MASK) > 0 {
+                let value_index = if cur_node.internal() & (bitmap & node::END_BIT_MASK) > 0 {
                     // key already exists!
                     ret = Some(self.results.replace(&result_hdl, result_index - 1, value));
+                    result_index - 1
                 } else {
                     cur_node.set_internal(bitmap & node::END_BIT_MASK);
                     self.results.insert(&mut result_hdl, result_index, value); // add result
                     self.len += 1;
-                }
+                    result_index
+                };
                 cur_node.result_ptr = result_hdl.offset;
                 self.trienodes.set(&cur_hdl, cur_index, cur_node); // save trie node
-                return ret;
+                return (ret, result_hdl, value_index);
             }
             // add a branch
 
@@ -206,7 +474,7 @@ impl<T: Sized> TreeBitmap<T> {
                 self.push_down(&mut cur_node);
             }
             let mut child_hdl = match cur_node.child_count() {
-                0 => self.trienodes.alloc(0),
+                0 => self.alloc_nodes(),
                 _ => cur_node.child_handle(),
             };
 
@@ -230,8 +498,7 @@ impl<T: Sized> TreeBitmap<T> {
             // prepare a child node
             let mut child_node = Node::new();
             child_node.make_endnode();
-            self.trienodes
-                .insert(&mut child_hdl, child_index, child_node); // save child
+            self.insert_node(&mut child_hdl, child_index, child_node); // save child
             cur_node.child_ptr = child_hdl.offset;
             self.trienodes.set(&cur_hdl, cur_index, cur_node); // save trie node
 
@@ -241,30 +508,104 @@ impl<T: Sized> TreeBitmap<T> {
         }
     }
 
+    /// Like ```insert```, but returns an error instead of aborting if memory
+    /// cannot be allocated. The trie is left unchanged on error.
+    pub fn try_insert(
+        &mut self,
+        nibbles: &[u8],
+        masklen: u32,
+        value: T,
+    ) -> Result<Option<T>, TryReserveError> {
+        check_exact_key(nibbles, masklen);
+        self.try_insert_unchecked(nibbles, masklen, value)
+    }
+
+    pub(crate) fn try_insert_unchecked(
+        &mut self,
+        nibbles: &[u8],
+        masklen: u32,
+        value: T,
+    ) -> Result<Option<T>, TryReserveError> {
+        self.reserve_insert(masklen)?;
+        Ok(self.insert_unchecked(nibbles, masklen, value))
+    }
+
+    /// Reserve memory for inserting a key of ```masklen``` bits.
+    fn reserve_insert(&mut self, masklen: u32) -> Result<(), TryReserveError> {
+        // an insert allocates at most a new single child node for each level,
+        // the nodes and results for the results pushed down from one end
+        // node, and moves a collection at most once into each bucket.
+        let levels = masklen as usize / 4 + 1;
+        self.trienodes.try_reserve(levels + 2, 2)?;
+        self.results.try_reserve(18, 2)
+    }
+
+    /// Returns the bytes used by nodes and results.
     pub fn mem_usage(&self) -> (usize, usize) {
         let node_bytes = self.trienodes.mem_usage();
         let result_bytes = self.results.mem_usage();
         (node_bytes, result_bytes)
     }
 
+    /// Returns the number of stored keys.
     pub fn len(&self) -> usize {
         self.len
     }
 
+    /// Returns true if no keys are stored.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns a reference to the value stored for ```nibbles```/```masklen```.
     pub fn exact_match(&self, nibbles: &[u8], masklen: u32) -> Option<&T> {
+        check_exact_key(nibbles, masklen);
+        self.exact_match_unchecked(nibbles, masklen)
+    }
+
+    pub(crate) fn exact_match_unchecked(&self, nibbles: &[u8], masklen: u32) -> Option<&T> {
+        match self.find(nibbles, masklen) {
+            Some((result_hdl, result_index)) => Some(self.results.get(&result_hdl, result_index)),
+            None => None,
+        }
+    }
+
+    /// Mutable version of ```exact_match```.
+    pub fn exact_match_mut(&mut self, nibbles: &[u8], masklen: u32) -> Option<&mut T> {
+        check_exact_key(nibbles, masklen);
+        self.exact_match_mut_unchecked(nibbles, masklen)
+    }
+
+    pub(crate) fn exact_match_mut_unchecked(
+        &mut self,
+        nibbles: &[u8],
+        masklen: u32,
+    ) -> Option<&mut T> {
+        match self.find(nibbles, masklen) {
+            Some((result_hdl, result_index)) => {
+                Some(self.results.get_mut(&result_hdl, result_index))
+            }
+            None => None,
+        }
+    }
+
+    /// Returns the location of the value stored for ```nibbles```/```masklen```.
+    fn find(&self, nibbles: &[u8], masklen: u32) -> Option<(AllocatorHandle, u32)> {
         let mut cur_hdl = self.root_handle();
         let mut cur_index = 0;
         let mut bits_left = masklen;
+        let mut depth = 0;
 
-        for nibble in nibbles {
+        loop {
+            let nibble = nibbles.get(depth).cloned().unwrap_or(0);
             let cur_node = self.trienodes.get(&cur_hdl, cur_index);
-            let bitmap = node::gen_bitmap(*nibble, cmp::min(bits_left, 4)) & node::END_BIT_MASK;
+            let bitmap = node::gen_bitmap(nibble, cmp::min(bits_left, 4)) & node::END_BIT_MASK;
             let reached_final_node = bits_left < 4 || (cur_node.is_endnode() && bits_left == 4);
 
             if reached_final_node {
                 match cur_node.match_internal(bitmap) {
                     MatchResult::Match(result_hdl, result_index, _) => {
-                        return Some(self.results.get(&result_hdl, result_index));
+                        return Some((result_hdl, result_index));
                     }
                     _ => return None,
                 }
@@ -275,16 +616,74 @@ impl<T: Sized> TreeBitmap<T> {
                     cur_hdl = child_hdl;
                     cur_index = child_index;
                     bits_left -= 4;
+                    depth += 1;
                 }
                 _ => return None,
             }
         }
-        None
+    }
+
+    /// Returns the entry for ```nibbles```/```masklen``` for in-place
+    /// manipulation.
+    pub fn entry(&mut self, nibbles: &[u8], masklen: u32) -> Entry<'_, T> {
+        check_exact_key(nibbles, masklen);
+        self.entry_unchecked(nibbles, masklen)
+    }
+
+    pub(crate) fn entry_unchecked(&mut self, nibbles: &[u8], masklen: u32) -> Entry<'_, T> {
+        match self.find(nibbles, masklen) {
+            Some((result_hdl, result_index)) => Entry::Occupied(OccupiedEntry {
+                inner: self,
+                nibbles: nibbles.to_vec(),
+                masklen,
+                result_hdl,
+                result_index,
+            }),
+            None => Entry::Vacant(VacantEntry {
+                inner: self,
+                nibbles: nibbles.to_vec(),
+                masklen,
+            }),
+        }
+    }
+
+    /// Like ```remove```, but returns an error instead of aborting if memory
+    /// cannot be allocated. The trie is left unchanged on error.
+    pub fn try_remove(
+        &mut self,
+        nibbles: &[u8],
+        masklen: u32,
+    ) -> Result<Option<T>, TryReserveError> {
+        check_exact_key(nibbles, masklen);
+        self.try_remove_unchecked(nibbles, masklen)
+    }
+
+    pub(crate) fn try_remove_unchecked(
+        &mut self,
+        nibbles: &[u8],
+        masklen: u32,
+    ) -> Result<Option<T>, TryReserveError> {
+        self.reserve_remove(masklen)?;
+        Ok(self.remove_unchecked(nibbles, masklen))
+    }
+
+    /// Reserve memory for removing a key of ```masklen``` bits.
+    fn reserve_remove(&mut self, masklen: u32) -> Result<(), TryReserveError> {
+        // a remove frees at most the single child node of each level, and
+        // shrinks at most one collection of nodes and one of results into a
+        // smaller bucket.
+        let levels = masklen as usize / 4 + 1;
+        self.trienodes.try_reserve(levels + 1, 1)?;
+        self.results.try_reserve(2, 1)
     }
 
     /// Remove prefix. Returns existing value if the prefix previously existed.
     pub fn remove(&mut self, nibbles: &[u8], masklen: u32) -> Option<T> {
-        debug_assert!(nibbles.len() >= (masklen / 4) as usize);
+        check_exact_key(nibbles, masklen);
+        self.remove_unchecked(nibbles, masklen)
+    }
+
+    pub(crate) fn remove_unchecked(&mut self, nibbles: &[u8], masklen: u32) -> Option<T> {
         let root_hdl = self.root_handle();
         let mut root_node = *self.trienodes.get(&root_hdl, 0);
         let ret = self.remove_child(&mut root_node, nibbles, masklen);
@@ -294,7 +693,7 @@ impl<T: Sized> TreeBitmap<T> {
 
     // remove child and result from node
     fn remove_child(&mut self, node: &mut Node, nibbles: &[u8], masklen: u32) -> Option<T> {
-        let nibble = nibbles[0];
+        let nibble = nibbles.first().cloned().unwrap_or(0);
         let bitmap = node::gen_bitmap(nibble, cmp::min(masklen, 4)) & node::END_BIT_MASK;
         let reached_final_node = masklen < 4 || (node.is_endnode() && masklen == 4);
 
@@ -316,195 +715,1316 @@ impl<T: Sized> TreeBitmap<T> {
 
         if let MatchResult::Chase(mut child_node_hdl, index) = node.match_external(bitmap) {
             let mut child_node = *self.trienodes.get(&child_node_hdl, index);
-            let ret = self.remove_child(&mut child_node, &nibbles[1..], masklen - 4);
-
-            if child_node.child_count() == 0 && !child_node.is_endnode() {
-                child_node.make_endnode();
-            }
-            if child_node.is_empty() {
-                self.trienodes.remove(&mut child_node_hdl, index);
-                node.unset_external(bitmap);
-                if child_node_hdl.len == 0 {
-                    // no child nodes
-                    self.trienodes.free(&mut child_node_hdl);
-                }
-                node.child_ptr = child_node_hdl.offset;
-            } else {
-                node.child_ptr = child_node_hdl.offset;
-                self.trienodes.set(&child_node_hdl, index, child_node);
-            }
+            let ret = self.remove_child(
+                &mut child_node,
+                nibbles.get(1..).unwrap_or(&[]),
+                masklen - 4,
+            );
+            self.save_child(node, bitmap, &mut child_node_hdl, index, child_node);
             ret
         } else {
             None
         }
     }
 
-    pub fn iter(&self) -> Iter<T> {
-        let root_hdl = self.root_handle();
-        let root_node = *self.trienodes.get(&root_hdl, 0);
-        Iter {
-            inner: self,
-            path: vec![PathElem {
-                node: root_node,
-                pos: 0,
-            }],
-            nibbles: vec![0],
-        }
+    /// Start or stop recording the changes to the layout of the trie nodes,
+    /// for keeping a ```NodeData``` in step with them.
+    pub(crate) fn log_node_ops(&mut self, enable: bool) {
+        self.node_log = if enable { Some(Vec::new()) } else { None };
     }
 
-    pub fn iter_mut(&mut self) -> IterMut<T> {
-        let root_hdl = self.root_handle();
-        let root_node = *self.trienodes.get(&root_hdl, 0);
-        IterMut {
-            inner: self,
-            path: vec![PathElem {
-                node: root_node,
-                pos: 0,
-            }],
-            nibbles: vec![0],
+    fn log_node_op(&mut self, op: NodeOp) {
+        if let Some(ref mut log) = self.node_log {
+            log.push(op);
         }
     }
-}
 
-#[derive(Debug)]
-struct PathElem {
-    node: Node,
-    pos: usize,
-}
+    fn alloc_nodes(&mut self) -> AllocatorHandle {
+        self.log_node_op(NodeOp::Alloc);
+        self.trienodes.alloc(0)
+    }
 
-pub struct Iter<'a, T: 'a> {
-    inner: &'a TreeBitmap<T>,
-    path: Vec<PathElem>,
-    nibbles: Vec<u8>,
-}
+    fn insert_node(&mut self, hdl: &mut AllocatorHandle, index: u32, node: Node) {
+        self.log_node_op(NodeOp::Insert(hdl.len, hdl.offset, index));
+        self.trienodes.insert(hdl, index, node);
+    }
 
-pub struct IterMut<'a, T: 'a> {
-    inner: &'a mut TreeBitmap<T>,
-    path: Vec<PathElem>,
-    nibbles: Vec<u8>,
-}
+    fn remove_node(&mut self, hdl: &mut AllocatorHandle, index: u32) {
+        self.log_node_op(NodeOp::Remove(hdl.len, hdl.offset, index));
+        self.trienodes.remove(hdl, index);
+    }
 
-#[cfg_attr(rustfmt, rustfmt_skip)]
-static PREFIX_OF_BIT: [u8; 32] = [// 0       1       2      3        4       5       6       7
-                                  0b0000, 0b0000, 0b1000, 0b0000, 0b0100, 0b1000, 0b1100, 0b0000,
-                                  // 8       9      10      11      12      13      14      15
-                                  0b0010, 0b0100, 0b0110, 0b1000, 0b1010, 0b1100, 0b1110,      0,
-                                  // 16      17      18      19      20      21      22      23
-                                  0b0000, 0b0001, 0b0010, 0b0011, 0b0100, 0b0101, 0b0110, 0b0111,
-                                  // 24      25      26      27      28      29      30      31
-                                  0b1000, 0b1001, 0b1010, 0b1011, 0b1100, 0b1101, 0b1110, 0b1111];
+    fn free_nodes(&mut self, hdl: &mut AllocatorHandle) {
+        self.log_node_op(NodeOp::Free(hdl.offset));
+        self.trienodes.free(hdl);
+    }
 
-fn next<T: Sized>(
-    trie: &TreeBitmap<T>,
-    path: &mut Vec<PathElem>,
-    nibbles: &mut Vec<u8>,
-) -> Option<(Vec<u8>, u32, AllocatorHandle, u32)> {
-    loop {
-        let mut path_elem = match path.pop() {
-            Some(elem) => elem,
-            None => return None,
-        };
-        let cur_node = path_elem.node;
-        let mut cur_pos = path_elem.pos;
-        nibbles.pop();
-        // optim:
-        if cur_pos == 0 && cur_node.result_count() == 0 {
-            path_elem.pos = 16;
-            cur_pos = 16;
-        }
-        if path_elem.pos == 32 {
-            continue;
-        }
-        let nibble = PREFIX_OF_BIT[path_elem.pos];
-        let bitmap = 1 << (31 - path_elem.pos);
+    fn release_nodes(&mut self, hdl: &mut AllocatorHandle) {
+        self.log_node_op(NodeOp::Release(hdl.len, hdl.offset));
+        self.trienodes.release(hdl);
+    }
 
-        path_elem.pos += 1;
-        nibbles.push(nibble);
-        path.push(path_elem);
-        // match internal
-        if cur_pos < 16 || cur_node.is_endnode() {
-            let match_result = cur_node.match_internal(bitmap);
-            if let MatchResult::Match(result_hdl, result_index, matching_bit) = match_result {
-                let bits_matched =
-                    ((path.len() as u32) - 1) * 4 + node::BIT_MATCH[matching_bit as usize];
-                return Some((nibbles.clone(), bits_matched, result_hdl, result_index));
+    /// Store a modified child node back into the child block of ```node```.
+    /// Childless nodes are made into end nodes and empty nodes are removed.
+    fn save_child(
+        &mut self,
+        node: &mut Node,
+        bitmap: u32,
+        child_node_hdl: &mut AllocatorHandle,
+        index: u32,
+        mut child_node: Node,
+    ) {
+        if child_node.child_count() == 0 && !child_node.is_endnode() {
+            child_node.make_endnode();
+        }
+        if child_node.is_empty() {
+            self.remove_node(child_node_hdl, index);
+            node.unset_external(bitmap);
+            if child_node_hdl.len == 0 {
+                // no child nodes
+                self.free_nodes(child_node_hdl);
             }
-        } else if let MatchResult::Chase(child_hdl, child_index) = cur_node.match_external(bitmap) {
-            let child_node = trie.trienodes.get(&child_hdl, child_index);
-            nibbles.push(0);
-            path.push(PathElem {
-                node: *child_node,
-                pos: 0,
-            });
+            node.child_ptr = child_node_hdl.offset;
+        } else {
+            node.child_ptr = child_node_hdl.offset;
+            self.trienodes.set(child_node_hdl, index, child_node);
         }
     }
-}
 
-impl<'a, T: 'a> Iterator for Iter<'a, T> {
-    type Item = (Vec<u8>, u32, &'a T); //(nibbles, masklen, &T)
+    /// Retain only the prefixes for which ```f``` returns true, removing the
+    /// others in a single traversal.
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&[u8], u32, &mut T) -> bool,
+    {
+        let root_hdl = self.root_handle();
+        self.retain_node(&root_hdl, 0, &mut Vec::new(), &mut f);
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        match next(self.inner, &mut self.path, &mut self.nibbles) {
-            Some((path, bits_matched, hdl, index)) => {
-                let value = self.inner.results.get(&hdl, index);
-                Some((path, bits_matched, value))
+    // remove results rejected by f from the node at index of hdl and its
+    // children. The node is written back after every change, before f or
+    // T::drop is called again, so the trie stays consistent if they panic.
+    fn retain_node<F>(
+        &mut self,
+        hdl: &AllocatorHandle,
+        index: u32,
+        nibbles: &mut Vec<u8>,
+        f: &mut F,
+    ) where
+        F: FnMut(&[u8], u32, &mut T) -> bool,
+    {
+        let mut node = *self.trienodes.get(hdl, index);
+        let bits_searched = (nibbles.len() as u32) * 4;
+        for pos in ADDRESS_ORDER.iter().map(|pos| *pos as usize) {
+            let bitmap = 1 << (31 - pos);
+            nibbles.push(PREFIX_OF_BIT[pos]);
+            if pos < 16 || node.is_endnode() {
+                if let MatchResult::Match(mut result_hdl, result_index, _) =
+                    node.match_internal(bitmap)
+                {
+                    let masklen = bits_searched + node::BIT_MATCH[pos];
+                    let value = self.results.get_mut(&result_hdl, result_index);
+                    if !f(nibbles, masklen, value) {
+                        node.unset_internal(bitmap);
+                        let value = self.results.remove(&mut result_hdl, result_index);
+                        if node.result_count() == 0 {
+                            self.results.free(&mut result_hdl);
+                        }
+                        node.result_ptr = result_hdl.offset;
+                        self.trienodes.set(hdl, index, node);
+                        self.len -= 1;
+                        drop(value);
+                    }
+                }
+            } else if let MatchResult::Chase(mut child_node_hdl, child_index) =
+                node.match_external(bitmap)
+            {
+                self.retain_node(&child_node_hdl, child_index, nibbles, f);
+                let child_node = *self.trienodes.get(&child_node_hdl, child_index);
+                self.save_child(
+                    &mut node,
+                    bitmap,
+                    &mut child_node_hdl,
+                    child_index,
+                    child_node,
+                );
+                self.trienodes.set(hdl, index, node);
             }
-            None => None,
+            nibbles.pop();
         }
     }
-}
-
-impl<'a, T: 'a> Iterator for IterMut<'a, T> {
-    type Item = (Vec<u8>, u32, &'a mut T); //(nibbles, masklen, &T)
 
-    fn next(&mut self) -> Option<Self::Item> {
-        match next(self.inner, &mut self.path, &mut self.nibbles) {
-            Some((path, bits_matched, hdl, index)) => unsafe {
-                let ptr: *mut T = self.inner.results.get_mut(&hdl, index);
-                let val_ref = &mut *ptr;
-                Some((path, bits_matched, val_ref))
-            },
-            None => None,
-        }
+    /// Remove the prefix ```nibbles```/```masklen``` along with all more
+    /// specific prefixes. Returns the number of prefixes removed.
+    pub fn remove_subtree(&mut self, nibbles: &[u8], masklen: u32) -> usize {
+        check_key(nibbles, masklen);
+        self.remove_subtree_unchecked(nibbles, masklen)
     }
-}
 
-pub struct IntoIter<T> {
-    inner: TreeBitmap<T>,
-    path: Vec<PathElem>,
-    nibbles: Vec<u8>,
-}
+    pub(crate) fn remove_subtree_unchecked(&mut self, nibbles: &[u8], masklen: u32) -> usize {
+        // drop the values only once the trie is updated, in case T::drop panics
+        self.drain_prefix_unchecked(nibbles, masklen).count()
+    }
 
-impl<'a, T: 'a> Iterator for IntoIter<T> {
-    type Item = (Vec<u8>, u32, T); //(nibbles, masklen, T)
+    /// Remove the prefix ```nibbles```/```masklen``` along with all more
+    /// specific prefixes, returning the removed items in address order.
+    pub fn drain_prefix(&mut self, nibbles: &[u8], masklen: u32) -> Drain<T> {
+        check_key(nibbles, masklen);
+        self.drain_prefix_unchecked(nibbles, masklen)
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        match next(&self.inner, &mut self.path, &mut self.nibbles) {
-            Some((path, bits_matched, hdl, index)) => {
-                let value = self.inner.results.get(&hdl, index);
-                let value = unsafe { ptr::read(value) };
-                Some((path, bits_matched, value))
-            }
-            None => None,
+    pub(crate) fn drain_prefix_unchecked(&mut self, nibbles: &[u8], masklen: u32) -> Drain<T> {
+        let mut items = Vec::new();
+        self.remove_prefix(nibbles, masklen, &mut |nibbles, masklen, value| {
+            items.push((nibbles.to_vec(), masklen, value))
+        });
+        Drain {
+            inner: items.into_iter(),
         }
     }
-}
 
-impl<T> IntoIterator for TreeBitmap<T> {
+    fn remove_prefix<F>(&mut self, nibbles: &[u8], masklen: u32, f: &mut F)
+    where
+        F: FnMut(&[u8], u32, T),
+    {
+        let root_hdl = self.root_handle();
+        let mut root_node = *self.trienodes.get(&root_hdl, 0);
+        self.remove_prefix_child(&mut root_node, nibbles, masklen, &mut Vec::new(), f);
+        self.trienodes.set(&root_hdl, 0, root_node);
+    }
+
+    // remove the subtree covered by nibbles/masklen from node
+    fn remove_prefix_child<F>(
+        &mut self,
+        node: &mut Node,
+        nibbles: &[u8],
+        masklen: u32,
+        path: &mut Vec<u8>,
+        f: &mut F,
+    ) where
+        F: FnMut(&[u8], u32, T),
+    {
+        let nibble = nibbles.first().cloned().unwrap_or(0);
+        let reached_final_node = masklen < 4 || (node.is_endnode() && masklen == 4);
+
+        if reached_final_node {
+            let bits_searched = (path.len() as u32) * 4;
+            let mask = prefix_mask(nibble, masklen);
+            for pos in ADDRESS_ORDER.iter().map(|pos| *pos as usize) {
+                let bitmap = 1 << (31 - pos);
+                if bitmap & mask == 0 {
+                    continue;
+                }
+                path.push(PREFIX_OF_BIT[pos]);
+                if pos < 16 || node.is_endnode() {
+                    if let MatchResult::Match(mut result_hdl, result_index, _) =
+                        node.match_internal(bitmap)
+                    {
+                        node.unset_internal(bitmap);
+                        let value = self.results.remove(&mut result_hdl, result_index);
+                        if node.result_count() == 0 {
+                            self.results.free(&mut result_hdl);
+                        }
+                        node.result_ptr = result_hdl.offset;
+                        self.len -= 1;
+                        f(path, bits_searched + node::BIT_MATCH[pos], value);
+                    }
+                } else if let MatchResult::Chase(mut child_node_hdl, index) =
+                    node.match_external(bitmap)
+                {
+                    let child_node = *self.trienodes.get(&child_node_hdl, index);
+                    self.release_node(&child_node, path, f);
+                    self.save_child(node, bitmap, &mut child_node_hdl, index, Node::new());
+                }
+                path.pop();
+            }
+            return;
+        }
+
+        let bitmap = node::gen_bitmap(nibble, 4) & node::END_BIT_MASK;
+        if let MatchResult::Chase(mut child_node_hdl, index) = node.match_external(bitmap) {
+            let mut child_node = *self.trienodes.get(&child_node_hdl, index);
+            path.push(nibble);
+            let nibbles = nibbles.get(1..).unwrap_or(&[]);
+            self.remove_prefix_child(&mut child_node, nibbles, masklen - 4, path, f);
+            path.pop();
+            self.save_child(node, bitmap, &mut child_node_hdl, index, child_node);
+        }
+    }
+
+    // move all results out of node and its children, and free their buckets
+    fn release_node<F>(&mut self, node: &Node, path: &mut Vec<u8>, f: &mut F)
+    where
+        F: FnMut(&[u8], u32, T),
+    {
+        let bits_searched = (path.len() as u32) * 4;
+        for pos in ADDRESS_ORDER.iter().map(|pos| *pos as usize) {
+            let bitmap = 1 << (31 - pos);
+            path.push(PREFIX_OF_BIT[pos]);
+            if pos < 16 || node.is_endnode() {
+                if let MatchResult::Match(result_hdl, result_index, _) = node.match_internal(bitmap)
+                {
+                    let value = unsafe { ptr::read(self.results.get(&result_hdl, result_index)) };
+                    f(path, bits_searched + node::BIT_MATCH[pos], value);
+                }
+            } else if let MatchResult::Chase(child_node_hdl, index) = node.match_external(bitmap) {
+                let child_node = *self.trienodes.get(&child_node_hdl, index);
+                self.release_node(&child_node, path, f);
+            }
+            path.pop();
+        }
+        if node.result_count() > 0 {
+            self.results.release(&mut node.result_handle());
+            self.len -= node.result_count() as usize;
+        }
+        if node.child_count() > 0 {
+            self.release_nodes(&mut node.child_handle());
+        }
+    }
+
+    /// Returns iterator over keys and values in address order: by the bits
+    /// of the key padded with zeros, then by key length.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            inner: self,
+            path: self.root_path(false),
+            nibbles: vec![0],
+            back_path: self.root_path(true),
+            back_nibbles: vec![0],
+            remaining: self.len,
+        }
+    }
+
+    /// Mutable version of ```iter```.
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        IterMut {
+            path: self.root_path(false),
+            nibbles: vec![0],
+            back_path: self.root_path(true),
+            back_nibbles: vec![0],
+            remaining: self.len,
+            inner: self,
+        }
+    }
+
+    /// Returns a cursor for walking the nodes of the trie, starting at the
+    /// root node.
+    pub fn trie_cursor(&self) -> TrieCursor<'_, T> {
+        TrieCursor::new(self)
+    }
+
+    /// Returns an iterator path starting at the root node, walking forward
+    /// from the first position or backward from the last.
+    fn root_path(&self, reverse: bool) -> Vec<PathElem> {
+        let root_node = *self.trienodes.get(&self.root_handle(), 0);
+        vec![PathElem {
+            node: root_node,
+            pos: if reverse { ADDRESS_ORDER.len() } else { 0 },
+            mask: !0,
+        }]
+    }
+
+    /// Returns iterator over the prefixes from ```nibbles```/```masklen```
+    /// onwards in address order, including the prefix itself.
+    pub fn iter_from(&self, nibbles: &[u8], masklen: u32) -> Range<'_, T> {
+        check_key(nibbles, masklen);
+        self.iter_from_unchecked(nibbles, masklen)
+    }
+
+    pub(crate) fn iter_from_unchecked(&self, nibbles: &[u8], masklen: u32) -> Range<'_, T> {
+        let (path, nibbles) = self.seek_path(nibbles, masklen, false, false);
+        Range {
+            inner: self,
+            path,
+            nibbles,
+        }
+    }
+
+    /// Returns iterator over the prefixes following ```nibbles```/```masklen```
+    /// in address order, excluding the prefix itself.
+    pub fn iter_after(&self, nibbles: &[u8], masklen: u32) -> Range<'_, T> {
+        check_key(nibbles, masklen);
+        self.iter_after_unchecked(nibbles, masklen)
+    }
+
+    pub(crate) fn iter_after_unchecked(&self, nibbles: &[u8], masklen: u32) -> Range<'_, T> {
+        let (path, nibbles) = self.seek_path(nibbles, masklen, true, false);
+        Range {
+            inner: self,
+            path,
+            nibbles,
+        }
+    }
+
+    /// Returns the first prefix after ```nibbles```/```masklen``` in address
+    /// order.
+    pub fn next_after(&self, nibbles: &[u8], masklen: u32) -> Option<(Vec<u8>, u32, &T)> {
+        check_key(nibbles, masklen);
+        self.next_after_unchecked(nibbles, masklen)
+    }
+
+    pub(crate) fn next_after_unchecked(
+        &self,
+        nibbles: &[u8],
+        masklen: u32,
+    ) -> Option<(Vec<u8>, u32, &T)> {
+        self.iter_after_unchecked(nibbles, masklen).next()
+    }
+
+    /// Returns the last prefix before ```nibbles```/```masklen``` in address
+    /// order.
+    pub fn prev_before(&self, nibbles: &[u8], masklen: u32) -> Option<(Vec<u8>, u32, &T)> {
+        check_key(nibbles, masklen);
+        self.prev_before_unchecked(nibbles, masklen)
+    }
+
+    pub(crate) fn prev_before_unchecked(
+        &self,
+        nibbles: &[u8],
+        masklen: u32,
+    ) -> Option<(Vec<u8>, u32, &T)> {
+        let (mut path, mut path_nibbles) = self.seek_path(nibbles, masklen, false, true);
+        match prev(self, &mut path, &mut path_nibbles) {
+            Some((path, bits_matched, hdl, index)) => {
+                Some((path, bits_matched, self.results.get(&hdl, index)))
+            }
+            None => None,
+        }
+    }
+
+    /// Returns the first prefix in address order.
+    pub fn first(&self) -> Option<(Vec<u8>, u32, &T)> {
+        self.iter().next()
+    }
+
+    /// Returns the last prefix in address order.
+    pub fn last(&self) -> Option<(Vec<u8>, u32, &T)> {
+        self.iter().next_back()
+    }
+
+    /// Removes and returns the first prefix in address order.
+    pub fn pop_first(&mut self) -> Option<(Vec<u8>, u32, T)> {
+        let (nibbles, masklen) = match self.first() {
+            Some((nibbles, masklen, _)) => (nibbles, masklen),
+            None => return None,
+        };
+        self.remove_unchecked(&nibbles, masklen)
+            .map(|value| (nibbles, masklen, value))
+    }
+
+    /// Descend towards ```nibbles```/```masklen``` and return an iterator path
+    /// positioned just before the prefix, or just after it if ```after``` is
+    /// set. The prefix does not need to be present.
+    fn seek_path(
+        &self,
+        nibbles: &[u8],
+        masklen: u32,
+        after: bool,
+        reverse: bool,
+    ) -> (Vec<PathElem>, Vec<u8>) {
+        let mut cur_node = *self.trienodes.get(&self.root_handle(), 0);
+        let mut bits_left = masklen;
+        let mut depth = 0;
+        let mut path = Vec::new();
+        let mut path_nibbles = Vec::new();
+
+        loop {
+            let nibble = nibbles.get(depth).cloned().unwrap_or(0);
+            if bits_left >= 4 && !cur_node.is_endnode() {
+                if let MatchResult::Chase(child_hdl, child_index) =
+                    cur_node.match_external(node::MATCH_MASKS[nibble as usize])
+                {
+                    let child_pos = ADDRESS_ORDER
+                        .iter()
+                        .position(|pos| *pos == 16 + nibble)
+                        .unwrap();
+                    path.push(PathElem {
+                        node: cur_node,
+                        pos: if reverse { child_pos } else { child_pos + 1 },
+                        mask: !0,
+                    });
+                    path_nibbles.push(nibble);
+                    cur_node = *self.trienodes.get(&child_hdl, child_index);
+                    bits_left -= 4;
+                    depth += 1;
+                    continue;
+                }
+            }
+            // the prefix within this node; deeper prefixes come after the 4 bit
+            // prefix they extend
+            let key = match bits_left {
+                0..=3 => (nibble & !(0xf >> bits_left), bits_left),
+                _ => (nibble, 4),
+            };
+            let after = after || bits_left > 4;
+            let pos = ADDRESS_ORDER
+                .iter()
+                .map(|pos| *pos as usize)
+                .take_while(|pos| {
+                    let pos_key = (PREFIX_OF_BIT[*pos], node::BIT_MATCH[*pos]);
+                    pos_key < key || (after && pos_key == key)
+                })
+                .count();
+            path.push(PathElem {
+                node: cur_node,
+                pos,
+                mask: !0,
+            });
+            path_nibbles.push(0);
+            return (path, path_nibbles);
+        }
+    }
+
+    /// Returns iterator over ```nibbles```/```masklen``` and all keys it is a
+    /// prefix of, in address order.
+    pub fn iter_prefix(&self, nibbles: &[u8], masklen: u32) -> Range<'_, T> {
+        check_key(nibbles, masklen);
+        self.iter_prefix_unchecked(nibbles, masklen)
+    }
+
+    pub(crate) fn iter_prefix_unchecked(&self, nibbles: &[u8], masklen: u32) -> Range<'_, T> {
+        let (path, nibbles) = self.prefix_path(nibbles, masklen);
+        Range {
+            inner: self,
+            path,
+            nibbles,
+        }
+    }
+
+    /// Mutable version of ```iter_prefix```.
+    pub fn iter_prefix_mut(&mut self, nibbles: &[u8], masklen: u32) -> RangeMut<'_, T> {
+        check_key(nibbles, masklen);
+        self.iter_prefix_mut_unchecked(nibbles, masklen)
+    }
+
+    pub(crate) fn iter_prefix_mut_unchecked(
+        &mut self,
+        nibbles: &[u8],
+        masklen: u32,
+    ) -> RangeMut<'_, T> {
+        let (path, nibbles) = self.prefix_path(nibbles, masklen);
+        RangeMut {
+            inner: self,
+            path,
+            nibbles,
+        }
+    }
+
+    /// Returns iterator over the prefixes directly below
+    /// ```nibbles```/```masklen```, i.e. the more specific prefixes that are
+    /// not covered by any other more specific prefix.
+    pub fn children(&self, nibbles: &[u8], masklen: u32) -> Children<'_, T> {
+        check_key(nibbles, masklen);
+        self.children_unchecked(nibbles, masklen)
+    }
+
+    pub(crate) fn children_unchecked(&self, nibbles: &[u8], masklen: u32) -> Children<'_, T> {
+        let (path, nibbles) = self.prefix_path(nibbles, masklen);
+        Children {
+            inner: self,
+            path,
+            nibbles,
+            masklen,
+        }
+    }
+
+    /// Descend to the node covering ```nibbles```/```masklen``` and return an
+    /// iterator path limited to the prefix and its more specifics.
+    fn prefix_path(&self, nibbles: &[u8], masklen: u32) -> (Vec<PathElem>, Vec<u8>) {
+        let mut cur_hdl = self.root_handle();
+        let mut cur_index = 0;
+        let mut bits_left = masklen;
+        let mut depth = 0;
+
+        loop {
+            let cur_node = *self.trienodes.get(&cur_hdl, cur_index);
+            let nibble = nibbles.get(depth).cloned().unwrap_or(0);
+
+            if bits_left < 4 || (cur_node.is_endnode() && bits_left == 4) {
+                let mut path_nibbles = nibbles[..depth].to_vec();
+                path_nibbles.push(0);
+                let path = vec![PathElem {
+                    node: cur_node,
+                    pos: 0,
+                    mask: prefix_mask(nibble, bits_left),
+                }];
+                return (path, path_nibbles);
+            }
+            if cur_node.is_endnode() {
+                return (Vec::new(), Vec::new());
+            }
+            match cur_node.match_external(node::MATCH_MASKS[nibble as usize]) {
+                MatchResult::Chase(child_hdl, child_index) => {
+                    bits_left -= 4;
+                    depth += 1;
+                    cur_hdl = child_hdl;
+                    cur_index = child_index;
+                }
+                _ => return (Vec::new(), Vec::new()),
+            }
+        }
+    }
+
+    /// Returns iterator over the keys intersecting the range from ```start```
+    /// to ```end``` inclusive, in address order. This includes keys covering
+    /// the range, keys within it, and keys partially overlapping it. The bounds
+    /// are padded with zeros.
+    pub fn overlapping_range(&self, start: &[u8], end: &[u8]) -> Overlapping<'_, T> {
+        check_key(start, 0);
+        check_key(end, 0);
+        self.overlapping_range_unchecked(start, end)
+    }
+
+    pub(crate) fn overlapping_range_unchecked(
+        &self,
+        start: &[u8],
+        end: &[u8],
+    ) -> Overlapping<'_, T> {
+        let len = cmp::max(start.len(), end.len());
+        let padded = |key: &[u8]| (0..len).map(|i| key.get(i).cloned().unwrap_or(0)).collect();
+        let (start, end): (Vec<u8>, Vec<u8>) = (padded(start), padded(end));
+        let path = if start <= end {
+            let root_node = *self.trienodes.get(&self.root_handle(), 0);
+            vec![PathElem {
+                node: root_node,
+                pos: 0,
+                mask: range_mask(&[], &start, &end),
+            }]
+        } else {
+            Vec::new()
+        };
+        Overlapping {
+            inner: self,
+            path,
+            nibbles: vec![0],
+            start,
+            end,
+        }
+    }
+
+    /// Returns iterator over all prefixes matching ```nibbles```, from least
+    /// to most specific.
+    pub fn matches(&self, nibbles: &[u8]) -> Matches<'_, T> {
+        check_key(nibbles, 0);
+        self.matches_unchecked(nibbles)
+    }
+
+    pub(crate) fn matches_unchecked(&self, nibbles: &[u8]) -> Matches<'_, T> {
+        let (node, pending) = self.first_match_node(nibbles);
+        Matches {
+            inner: self,
+            nibbles: nibbles.to_vec(),
+            depth: 0,
+            node,
+            pending,
+        }
+    }
+
+    /// Mutable version of ```matches```.
+    pub fn matches_mut(&mut self, nibbles: &[u8]) -> MatchesMut<'_, T> {
+        check_key(nibbles, 0);
+        self.matches_mut_unchecked(nibbles)
+    }
+
+    pub(crate) fn matches_mut_unchecked(&mut self, nibbles: &[u8]) -> MatchesMut<'_, T> {
+        let (node, pending) = self.first_match_node(nibbles);
+        MatchesMut {
+            inner: self,
+            nibbles: nibbles.to_vec(),
+            depth: 0,
+            node,
+            pending,
+        }
+    }
+
+    /// Returns the root node and its internal bits matching the first nibble.
+    fn first_match_node(&self, nibbles: &[u8]) -> (Option<Node>, u32) {
+        let root_node = *self.trienodes.get(&self.root_handle(), 0);
+        let pending = root_node.internal() & match_mask(nibbles, 0);
+        (Some(root_node), pending)
+    }
+}
+
+/// A view into a single entry of a ```TreeBitmap```, which may be vacant or
+/// occupied.
+pub enum Entry<'a, T: 'a> {
+    Occupied(OccupiedEntry<'a, T>),
+    Vacant(VacantEntry<'a, T>),
+}
+
+/// A view into an occupied entry of a ```TreeBitmap```.
+pub struct OccupiedEntry<'a, T: 'a> {
+    inner: &'a mut TreeBitmap<T>,
+    nibbles: Vec<u8>,
+    masklen: u32,
+    result_hdl: AllocatorHandle,
+    result_index: u32,
+}
+
+/// A view into a vacant entry of a ```TreeBitmap```.
+pub struct VacantEntry<'a, T: 'a> {
+    inner: &'a mut TreeBitmap<T>,
+    nibbles: Vec<u8>,
+    masklen: u32,
+}
+
+impl<'a, T: 'a> OccupiedEntry<'a, T> {
+    /// Gets a reference to the value in the entry.
+    pub fn get(&self) -> &T {
+        self.inner.results.get(&self.result_hdl, self.result_index)
+    }
+
+    /// Gets a mutable reference to the value in the entry.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.inner
+            .results
+            .get_mut(&self.result_hdl, self.result_index)
+    }
+
+    /// Converts the entry into a mutable reference to its value, bound to the
+    /// lifetime of the trie.
+    pub fn into_mut(self) -> &'a mut T {
+        self.inner
+            .results
+            .get_mut(&self.result_hdl, self.result_index)
+    }
+
+    /// Sets the value of the entry, and returns the entry's old value.
+    pub fn insert(&mut self, value: T) -> T {
+        self.inner
+            .results
+            .replace(&self.result_hdl, self.result_index, value)
+    }
+
+    /// Takes the value of the entry out of the trie, and returns it.
+    pub fn remove(self) -> T {
+        match self.inner.remove(&self.nibbles, self.masklen) {
+            Some(value) => value,
+            None => unreachable!(),
+        }
+    }
+}
+
+impl<'a, T: 'a> VacantEntry<'a, T> {
+    /// Sets the value of the entry, and returns a mutable reference to it.
+    pub fn insert(self, value: T) -> &'a mut T {
+        let (_, result_hdl, result_index) =
+            self.inner.insert_result(&self.nibbles, self.masklen, value);
+        self.inner.results.get_mut(&result_hdl, result_index)
+    }
+}
+
+#[derive(Debug)]
+struct PathElem {
+    node: Node,
+    pos: usize,
+    mask: u32, // bits of node to visit
+}
+
+/// Iterator over keys and values in address order.
+pub struct Iter<'a, T: 'a> {
+    inner: &'a TreeBitmap<T>,
+    path: Vec<PathElem>,
+    nibbles: Vec<u8>,
+    back_path: Vec<PathElem>,
+    back_nibbles: Vec<u8>,
+    remaining: usize,
+}
+
+/// Mutable iterator over keys and values in address order.
+pub struct IterMut<'a, T: 'a> {
+    inner: &'a mut TreeBitmap<T>,
+    path: Vec<PathElem>,
+    nibbles: Vec<u8>,
+    back_path: Vec<PathElem>,
+    back_nibbles: Vec<u8>,
+    remaining: usize,
+}
+
+/// Iterator over a range of keys and values in address order.
+pub struct Range<'a, T: 'a> {
+    inner: &'a TreeBitmap<T>,
+    path: Vec<PathElem>,
+    nibbles: Vec<u8>,
+}
+
+/// Mutable iterator over a range of keys and values in address order.
+pub struct RangeMut<'a, T: 'a> {
+    inner: &'a mut TreeBitmap<T>,
+    path: Vec<PathElem>,
+    nibbles: Vec<u8>,
+}
+
+#[rustfmt::skip]
+static PREFIX_OF_BIT: [u8; 32] = [// 0       1       2      3        4       5       6       7
+                                  0b0000, 0b0000, 0b1000, 0b0000, 0b0100, 0b1000, 0b1100, 0b0000,
+                                  // 8       9      10      11      12      13      14      15
+                                  0b0010, 0b0100, 0b0110, 0b1000, 0b1010, 0b1100, 0b1110,      0,
+                                  // 16      17      18      19      20      21      22      23
+                                  0b0000, 0b0001, 0b0010, 0b0011, 0b0100, 0b0101, 0b0110, 0b0111,
+                                  // 24      25      26      27      28      29      30      31
+                                  0b1000, 0b1001, 0b1010, 0b1011, 0b1100, 0b1101, 0b1110, 0b1111];
+
+/// Bit positions of a node, in the order the prefixes they represent are
+/// visited by ```next```: by network address, then by mask length. For each
+/// nibble value the shorter prefixes padding out to it come first, followed by
+/// the 4 bit prefix or child node for that nibble.
+#[rustfmt::skip]
+static ADDRESS_ORDER: [u8; 31] = [ 0,  1,  3,  7, 16, // 0000
+                                  17,                 // 0001
+                                   8, 18,             // 0010
+                                  19,                 // 0011
+                                   4,  9, 20,         // 0100
+                                  21,                 // 0101
+                                  10, 22,             // 0110
+                                  23,                 // 0111
+                                   2,  5, 11, 24,     // 1000
+                                  25,                 // 1001
+                                  12, 26,             // 1010
+                                  27,                 // 1011
+                                   6, 13, 28,         // 1100
+                                  29,                 // 1101
+                                  14, 30,             // 1110
+                                  31,                 // 1111
+];
+
+/// Panics unless ```nibbles``` holds at least ```masklen``` bits and only
+/// contains values below 16.
+fn check_key(nibbles: &[u8], masklen: u32) {
+    assert!(
+        masklen as usize <= nibbles.len() * 4,
+        "masklen {} exceeds the {} bits of the key",
+        masklen,
+        nibbles.len() * 4
+    );
+    assert!(
+        nibbles.iter().all(|nibble| *nibble < 16),
+        "key contains values that are not nibbles"
+    );
+}
+
+/// Panics unless ```nibbles```/```masklen``` is a valid key with no bits set
+/// past ```masklen```.
+fn check_exact_key(nibbles: &[u8], masklen: u32) {
+    check_key(nibbles, masklen);
+    assert!(
+        nibbles.iter().enumerate().all(|(i, nibble)| {
+            let bits = cmp::min(masklen.saturating_sub(i as u32 * 4), 4);
+            nibble & (0xf >> bits) == 0
+        }),
+        "key has bits set past masklen {}",
+        masklen
+    );
+}
+
+/// Returns the bits of a node at ```depth``` matching ```nibbles```. Past the
+/// last nibble only the prefix of the full key length matches.
+fn match_mask(nibbles: &[u8], depth: usize) -> u32 {
+    match nibbles.get(depth) {
+        Some(nibble) => node::MATCH_MASKS[*nibble as usize],
+        None => node::MSB,
+    }
+}
+
+/// Returns the bits of a node representing prefixes of at most ```masklen```
+/// bits.
+fn length_mask(masklen: u32) -> u32 {
+    match masklen {
+        0..=3 => !(!0 >> ((1 << (masklen + 1)) - 1)),
+        _ => !0,
+    }
+}
+
+/// Returns the bits of a node representing prefixes of at least ```masklen```
+/// bits.
+fn min_length_mask(masklen: u32) -> u32 {
+    if masklen == 0 {
+        return !0;
+    }
+    node::BIT_MATCH
+        .iter()
+        .enumerate()
+        .filter(|&(_, &bits)| bits >= masklen)
+        .fold(0, |mask, (pos, _)| mask | node::MSB >> pos)
+}
+
+/// Returns the bits of a node which are covered by the first ```masklen```
+/// bits of ```nibble```.
+fn prefix_mask(nibble: u8, masklen: u32) -> u32 {
+    let shift = 4 - masklen;
+    let mut mask = 0;
+    for (pos, prefix) in PREFIX_OF_BIT.iter().enumerate() {
+        if pos != 15 && node::BIT_MATCH[pos] >= masklen && prefix >> shift == nibble >> shift {
+            mask |= 1 << (31 - pos);
+        }
+    }
+    mask
+}
+
+/// Returns the bits of the node at ```path``` whose prefixes intersect the
+/// range from ```start``` to ```end```, given that the path itself intersects
+/// it. Keys are padded with zeros.
+fn range_mask(path: &[u8], start: &[u8], end: &[u8]) -> u32 {
+    let depth = path.len();
+    let low = if start.starts_with(path) {
+        start.get(depth).cloned().unwrap_or(0)
+    } else {
+        0
+    };
+    let high = if end.starts_with(path) {
+        end.get(depth).cloned().unwrap_or(0)
+    } else {
+        0xf
+    };
+    let mut mask = 0;
+    for (pos, &prefix) in PREFIX_OF_BIT.iter().enumerate() {
+        let last = prefix | (0xf >> node::BIT_MATCH[pos]);
+        if last >= low && prefix <= high {
+            mask |= node::MSB >> pos;
+        }
+    }
+    mask
+}
+
+fn next<T: Sized>(
+    trie: &TreeBitmap<T>,
+    path: &mut Vec<PathElem>,
+    nibbles: &mut Vec<u8>,
+) -> Option<(Vec<u8>, u32, AllocatorHandle, u32)> {
+    walk(trie, path, nibbles, false, None)
+}
+
+/// Like ```next```, but walks the trie in reverse address order. The ```pos```
+/// of each path element counts the positions left to visit.
+fn prev<T: Sized>(
+    trie: &TreeBitmap<T>,
+    path: &mut Vec<PathElem>,
+    nibbles: &mut Vec<u8>,
+) -> Option<(Vec<u8>, u32, AllocatorHandle, u32)> {
+    walk(trie, path, nibbles, true, None)
+}
+
+/// Like ```next```, but prunes the subtrees of child nodes which do not
+/// intersect the range from ```start``` to ```end```.
+fn next_within<T: Sized>(
+    trie: &TreeBitmap<T>,
+    path: &mut Vec<PathElem>,
+    nibbles: &mut Vec<u8>,
+    start: &[u8],
+    end: &[u8],
+) -> Option<(Vec<u8>, u32, AllocatorHandle, u32)> {
+    walk(trie, path, nibbles, false, Some((start, end)))
+}
+
+fn walk<T: Sized>(
+    trie: &TreeBitmap<T>,
+    path: &mut Vec<PathElem>,
+    nibbles: &mut Vec<u8>,
+    reverse: bool,
+    bounds: Option<(&[u8], &[u8])>,
+) -> Option<(Vec<u8>, u32, AllocatorHandle, u32)> {
+    loop {
+        let mut path_elem = path.pop()?;
+        let cur_node = path_elem.node;
+        nibbles.pop();
+        if (reverse && path_elem.pos == 0) || (!reverse && path_elem.pos == ADDRESS_ORDER.len()) {
+            continue;
+        }
+        if reverse {
+            path_elem.pos -= 1;
+        }
+        let cur_pos = ADDRESS_ORDER[path_elem.pos] as usize;
+        let nibble = PREFIX_OF_BIT[cur_pos];
+        let bitmap = 1 << (31 - cur_pos);
+
+        let mask = path_elem.mask;
+        if !reverse {
+            path_elem.pos += 1;
+        }
+        nibbles.push(nibble);
+        path.push(path_elem);
+        // optim: skip bits not present in the node
+        if bitmap & mask & (cur_node.internal() | cur_node.external()) == 0 {
+            continue;
+        }
+        // match internal
+        if cur_pos < 16 || cur_node.is_endnode() {
+            let match_result = cur_node.match_internal(bitmap);
+            if let MatchResult::Match(result_hdl, result_index, matching_bit) = match_result {
+                let bits_matched =
+                    ((nibbles.len() as u32) - 1) * 4 + node::BIT_MATCH[matching_bit as usize];
+                return Some((nibbles.clone(), bits_matched, result_hdl, result_index));
+            }
+        } else if let MatchResult::Chase(child_hdl, child_index) = cur_node.match_external(bitmap) {
+            let child_node = trie.trienodes.get(&child_hdl, child_index);
+            let mask = match bounds {
+                Some((start, end)) => range_mask(nibbles, start, end),
+                None => !0,
+            };
+            nibbles.push(0);
+            path.push(PathElem {
+                node: *child_node,
+                pos: if reverse { ADDRESS_ORDER.len() } else { 0 },
+                mask,
+            });
+        }
+    }
+}
+
+impl<'a, T: 'a> Iterator for Iter<'a, T> {
+    type Item = (Vec<u8>, u32, &'a T); //(nibbles, masklen, &T)
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        match next(self.inner, &mut self.path, &mut self.nibbles) {
+            Some((path, bits_matched, hdl, index)) => {
+                self.remaining -= 1;
+                let value = self.inner.results.get(&hdl, index);
+                Some((path, bits_matched, value))
+            }
+            None => None,
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T: 'a> DoubleEndedIterator for Iter<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        match prev(self.inner, &mut self.back_path, &mut self.back_nibbles) {
+            Some((path, bits_matched, hdl, index)) => {
+                self.remaining -= 1;
+                let value = self.inner.results.get(&hdl, index);
+                Some((path, bits_matched, value))
+            }
+            None => None,
+        }
+    }
+}
+
+impl<'a, T: 'a> ExactSizeIterator for Iter<'a, T> {}
+
+impl<'a, T: 'a> Iterator for IterMut<'a, T> {
+    type Item = (Vec<u8>, u32, &'a mut T); //(nibbles, masklen, &T)
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        match next(self.inner, &mut self.path, &mut self.nibbles) {
+            Some((path, bits_matched, hdl, index)) => unsafe {
+                self.remaining -= 1;
+                let ptr: *mut T = self.inner.results.get_mut(&hdl, index);
+                let val_ref = &mut *ptr;
+                Some((path, bits_matched, val_ref))
+            },
+            None => None,
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T: 'a> DoubleEndedIterator for IterMut<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        // both ends stop once all items are returned, so no item is
+        // returned twice
+        if self.remaining == 0 {
+            return None;
+        }
+        match prev(self.inner, &mut self.back_path, &mut self.back_nibbles) {
+            Some((path, bits_matched, hdl, index)) => unsafe {
+                self.remaining -= 1;
+                let ptr: *mut T = self.inner.results.get_mut(&hdl, index);
+                let val_ref = &mut *ptr;
+                Some((path, bits_matched, val_ref))
+            },
+            None => None,
+        }
+    }
+}
+
+impl<'a, T: 'a> ExactSizeIterator for IterMut<'a, T> {}
+
+impl<'a, T: 'a> Iterator for Range<'a, T> {
+    type Item = (Vec<u8>, u32, &'a T); //(nibbles, masklen, &T)
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match next(self.inner, &mut self.path, &mut self.nibbles) {
+            Some((path, bits_matched, hdl, index)) => {
+                let value = self.inner.results.get(&hdl, index);
+                Some((path, bits_matched, value))
+            }
+            None => None,
+        }
+    }
+}
+
+impl<'a, T: 'a> Iterator for RangeMut<'a, T> {
+    type Item = (Vec<u8>, u32, &'a mut T); //(nibbles, masklen, &T)
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match next(self.inner, &mut self.path, &mut self.nibbles) {
+            Some((path, bits_matched, hdl, index)) => unsafe {
+                let ptr: *mut T = self.inner.results.get_mut(&hdl, index);
+                let val_ref = &mut *ptr;
+                Some((path, bits_matched, val_ref))
+            },
+            None => None,
+        }
+    }
+}
+
+/// Iterator over the immediate more specific keys of a key.
+pub struct Children<'a, T: 'a> {
+    inner: &'a TreeBitmap<T>,
+    path: Vec<PathElem>,
+    nibbles: Vec<u8>,
+    masklen: u32,
+}
+
+impl<'a, T: 'a> Iterator for Children<'a, T> {
+    type Item = (Vec<u8>, u32, &'a T); //(nibbles, masklen, &T)
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (path, bits_matched, hdl, index) =
+                next(self.inner, &mut self.path, &mut self.nibbles)?;
+            if bits_matched == self.masklen {
+                continue;
+            }
+            // skip the more specifics of this prefix
+            if let Some(path_elem) = self.path.last_mut() {
+                let bits_searched = (self.nibbles.len() as u32 - 1) * 4;
+                let nibble = self.nibbles[self.nibbles.len() - 1];
+                path_elem.mask &= !prefix_mask(nibble, bits_matched - bits_searched);
+            }
+            return Some((path, bits_matched, self.inner.results.get(&hdl, index)));
+        }
+    }
+}
+
+/// Iterator over keys intersecting a range, in address order.
+pub struct Overlapping<'a, T: 'a> {
+    inner: &'a TreeBitmap<T>,
+    path: Vec<PathElem>,
+    nibbles: Vec<u8>,
+    start: Vec<u8>,
+    end: Vec<u8>,
+}
+
+impl<'a, T: 'a> Iterator for Overlapping<'a, T> {
+    type Item = (Vec<u8>, u32, &'a T); //(nibbles, masklen, &T)
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match next_within(
+            self.inner,
+            &mut self.path,
+            &mut self.nibbles,
+            &self.start,
+            &self.end,
+        ) {
+            Some((path, bits_matched, hdl, index)) => {
+                let value = self.inner.results.get(&hdl, index);
+                Some((path, bits_matched, value))
+            }
+            None => None,
+        }
+    }
+}
+
+/// Iterator over keys matching a bit string, from least to most specific.
+pub struct Matches<'a, T: 'a> {
+    inner: &'a TreeBitmap<T>,
+    nibbles: Vec<u8>,
+    depth: usize,
+    node: Option<Node>,
+    pending: u32,
+}
+
+/// Mutable iterator over keys matching a bit string, from least to most
+/// specific.
+pub struct MatchesMut<'a, T: 'a> {
+    inner: &'a mut TreeBitmap<T>,
+    nibbles: Vec<u8>,
+    depth: usize,
+    node: Option<Node>,
+    pending: u32,
+}
+
+/// Returns the bits matched and the result location of the internal bitmap
+/// hit at ```bit_index``` of a node at ```depth```.
+fn match_location(node: &Node, depth: usize, bit_index: u32) -> (u32, AllocatorHandle, u32) {
+    let result_index = match bit_index {
+        0 => 0,
+        _ => (node.internal() >> (32 - bit_index)).count_ones(),
+    };
+    let bits_matched = (depth as u32) * 4 + node::BIT_MATCH[bit_index as usize];
+    (bits_matched, node.result_handle(), result_index)
+}
+
+/// Follows the same path as ```longest_match```, returning every internal
+/// bitmap hit on the way down instead of only the last one.
+fn next_match<T: Sized>(
+    trie: &TreeBitmap<T>,
+    nibbles: &[u8],
+    depth: &mut usize,
+    cur: &mut Option<Node>,
+    pending: &mut u32,
+) -> Option<(u32, AllocatorHandle, u32)> {
+    loop {
+        let cur_node = (*cur)?;
+        if *pending > 0 {
+            let bit_index = pending.leading_zeros();
+            *pending ^= node::MSB >> bit_index;
+            return Some(match_location(&cur_node, *depth, bit_index));
+        }
+        *cur = None;
+        if cur_node.is_endnode() || *depth >= nibbles.len() {
+            return None;
+        }
+        let match_mask = node::MATCH_MASKS[nibbles[*depth] as usize];
+        if let MatchResult::Chase(child_hdl, child_index) = cur_node.match_external(match_mask) {
+            let child_node = *trie.trienodes.get(&child_hdl, child_index);
+            *depth += 1;
+            *pending = child_node.internal() & self::match_mask(nibbles, *depth);
+            *cur = Some(child_node);
+        }
+    }
+}
+
+impl<'a, T: 'a> Iterator for Matches<'a, T> {
+    type Item = (u32, &'a T); //(masklen, &T)
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match next_match(
+            self.inner,
+            &self.nibbles,
+            &mut self.depth,
+            &mut self.node,
+            &mut self.pending,
+        ) {
+            Some((bits_matched, hdl, index)) => {
+                let value = self.inner.results.get(&hdl, index);
+                Some((bits_matched, value))
+            }
+            None => None,
+        }
+    }
+}
+
+impl<'a, T: 'a> Iterator for MatchesMut<'a, T> {
+    type Item = (u32, &'a mut T); //(masklen, &mut T)
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match next_match(
+            self.inner,
+            &self.nibbles,
+            &mut self.depth,
+            &mut self.node,
+            &mut self.pending,
+        ) {
+            Some((bits_matched, hdl, index)) => unsafe {
+                let ptr: *mut T = self.inner.results.get_mut(&hdl, index);
+                let val_ref = &mut *ptr;
+                Some((bits_matched, val_ref))
+            },
+            None => None,
+        }
+    }
+}
+
+/// Iterator over keys and values removed by ```drain_prefix```.
+pub struct Drain<T> {
+    inner: vec::IntoIter<(Vec<u8>, u32, T)>,
+}
+
+impl<T> Iterator for Drain<T> {
+    type Item = (Vec<u8>, u32, T); //(nibbles, masklen, T)
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+/// Owning iterator over keys and values in address order.
+pub struct IntoIter<T> {
+    inner: TreeBitmap<T>,
+    path: Vec<PathElem>,
+    nibbles: Vec<u8>,
+    back_path: Vec<PathElem>,
+    back_nibbles: Vec<u8>,
+    remaining: usize,
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = (Vec<u8>, u32, T); //(nibbles, masklen, T)
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        match next(&self.inner, &mut self.path, &mut self.nibbles) {
+            Some((path, bits_matched, hdl, index)) => {
+                self.remaining -= 1;
+                let value = self.inner.results.get(&hdl, index);
+                let value = unsafe { ptr::read(value) };
+                Some((path, bits_matched, value))
+            }
+            None => None,
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<T> DoubleEndedIterator for IntoIter<T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        match prev(&self.inner, &mut self.back_path, &mut self.back_nibbles) {
+            Some((path, bits_matched, hdl, index)) => {
+                self.remaining -= 1;
+                let value = self.inner.results.get(&hdl, index);
+                let value = unsafe { ptr::read(value) };
+                Some((path, bits_matched, value))
+            }
+            None => None,
+        }
+    }
+}
+
+impl<T> ExactSizeIterator for IntoIter<T> {}
+
+impl<T> IntoIterator for TreeBitmap<T> {
     type Item = (Vec<u8>, u32, T); //(nibbles, masklen, T)
     type IntoIter = IntoIter<T>;
 
     fn into_iter(mut self) -> IntoIter<T> {
-        let root_hdl = self.root_handle();
-        let root_node = *self.trienodes.get(&root_hdl, 0);
         self.should_drop = false; // IntoIter will drop contents
         IntoIter {
-            inner: self,
-            path: vec![PathElem {
-                node: root_node,
-                pos: 0,
-            }],
+            path: self.root_path(false),
             nibbles: vec![0],
+            back_path: self.root_path(true),
+            back_nibbles: vec![0],
+            remaining: self.len,
+            inner: self,
         }
     }
 }
@@ -515,6 +2035,80 @@ impl<T> Drop for IntoIter<T> {
     }
 }
 
+impl<T> Default for TreeBitmap<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Clone> Clone for TreeBitmap<T> {
+    fn clone(&self) -> Self {
+        let mut other = TreeBitmap::with_capacity(0);
+        let root_hdl = self.root_handle();
+        let root_node = self.clone_node(self.trienodes.get(&root_hdl, 0), &mut other);
+        other.trienodes.set(&root_hdl, 0, root_node);
+        other.len = self.len;
+        other
+    }
+}
+
+impl<T: Clone> TreeBitmap<T> {
+    /// Deep copy ```node``` along with its results and child nodes into
+    /// ```dst```, returning the copied node.
+    fn clone_node(&self, node: &Node, dst: &mut TreeBitmap<T>) -> Node {
+        let mut new_node = *node;
+        if node.result_count() > 0 {
+            let result_hdl = node.result_handle();
+            let new_result_hdl = dst.results.alloc(result_hdl.len);
+            for i in 0..result_hdl.len {
+                let value = self.results.get(&result_hdl, i).clone();
+                dst.results.set(&new_result_hdl, i, value);
+            }
+            new_node.result_ptr = new_result_hdl.offset;
+        }
+        if node.child_count() > 0 {
+            let child_hdl = node.child_handle();
+            let new_child_hdl = dst.trienodes.alloc(child_hdl.len);
+            for i in 0..child_hdl.len {
+                let child_node = self.clone_node(self.trienodes.get(&child_hdl, i), dst);
+                dst.trienodes.set(&new_child_hdl, i, child_node);
+            }
+            new_node.child_ptr = new_child_hdl.offset;
+        }
+        new_node
+    }
+}
+
+/// Returns the nibbles of a key yielded by ```Iter```, without the trailing
+/// zero nibbles of the node holding it, which depend on the trie layout.
+fn key_nibbles(nibbles: &[u8], masklen: u32) -> &[u8] {
+    &nibbles[..cmp::min(nibbles.len(), (masklen as usize + 3) / 4)]
+}
+
+/// Tries are equal if they hold the same keys with equal values, regardless
+/// of their layout.
+impl<T: PartialEq> PartialEq for TreeBitmap<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len
+            && self.iter().zip(other.iter()).all(|(a, b)| {
+                a.1 == b.1 && key_nibbles(&a.0, a.1) == key_nibbles(&b.0, b.1) && a.2 == b.2
+            })
+    }
+}
+
+impl<T: Eq> Eq for TreeBitmap<T> {}
+
+impl<T: Hash> Hash for TreeBitmap<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.len.hash(state);
+        for (nibbles, masklen, value) in self.iter() {
+            key_nibbles(&nibbles, masklen).hash(state);
+            masklen.hash(state);
+            value.hash(state);
+        }
+    }
+}
+
 impl<T> Drop for TreeBitmap<T> {
     fn drop(&mut self) {
         if self.should_drop {
@@ -530,6 +2124,10 @@ impl<T> Drop for TreeBitmap<T> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::cell::RefCell;
+    use std::mem;
+    use std::panic::{self, AssertUnwindSafe};
+    use std::rc::Rc;
 
     #[test]
     fn len() {
@@ -594,6 +2192,19 @@ mod tests {
         assert_eq!(iter.next(), None);
     }
 
+    #[test]
+    fn eq_after_push_down() {
+        let mut a: TreeBitmap<u32> = TreeBitmap::new();
+        a.insert(&[0, 10], 8, 1);
+        let mut b = a.clone();
+        // pushes the /8 down a level, which removing the /12 does not undo
+        b.insert(&[0, 10, 1], 12, 2);
+        b.remove(&[0, 10, 1], 12);
+        assert!(a == b);
+        b.insert(&[0, 10], 8, 3);
+        assert!(a != b);
+    }
+
     #[test]
     fn into_iter() {
         let mut tbm: TreeBitmap<u32> = TreeBitmap::new();
@@ -614,6 +2225,137 @@ mod tests {
         assert_eq!(iter.next(), None);
     }
 
+    #[test]
+    fn retain() {
+        let mut tbm: TreeBitmap<u32> = TreeBitmap::new();
+        let (nibbles_a, mask_a) = (&[0, 0, 0, 0, 0, 0], 0);
+        let (nibbles_b, mask_b) = (&[0, 10, 0, 0, 0, 0], 8);
+        let (nibbles_c, mask_c) = (&[0, 10, 0, 10, 0, 10], 24);
+        let (nibbles_d, mask_d) = (&[0, 10, 0, 10, 1, 11], 24);
+        tbm.insert(nibbles_a, mask_a, 1);
+        tbm.insert(nibbles_b, mask_b, 2);
+        tbm.insert(nibbles_c, mask_c, 3);
+        tbm.insert(nibbles_d, mask_d, 4);
+
+        tbm.retain(|_, _, value| *value % 2 == 0);
+        assert_eq!(tbm.len(), 2);
+        assert_eq!(tbm.exact_match(nibbles_b, mask_b), Some(&2));
+        assert_eq!(tbm.exact_match(nibbles_d, mask_d), Some(&4));
+        assert_eq!(tbm.longest_match(nibbles_c), Some((mask_b, &2)));
+
+        tbm.retain(|_, _, _| false);
+        assert_eq!(tbm.len(), 0);
+        assert!(tbm.root_node().is_empty());
+    }
+
+    struct Tracked {
+        id: u32,
+        panic_on_drop: bool,
+        dropped: Rc<RefCell<Vec<u32>>>,
+    }
+    impl Drop for Tracked {
+        fn drop(&mut self) {
+            self.dropped.borrow_mut().push(self.id);
+            if self.panic_on_drop {
+                panic!("dropping id {}", self.id);
+            }
+        }
+    }
+
+    // trie of n random prefixes with ids 0..n, and their keys
+    fn tracked_trie(
+        n: u32,
+        dropped: &Rc<RefCell<Vec<u32>>>,
+    ) -> (TreeBitmap<Tracked>, Vec<(Vec<u8>, u32)>) {
+        let mut tbm = TreeBitmap::new();
+        let mut keys = Vec::new();
+        let mut seed: u32 = 0xcafe_babe;
+        for id in 0..n {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            let masklen = seed % 33;
+            let bits = if masklen == 0 {
+                0
+            } else {
+                seed.rotate_left(9) & (!0 << (32 - masklen))
+            };
+            let nibbles: Vec<u8> = (0..8).map(|i| (bits >> (28 - i * 4)) as u8 & 0xf).collect();
+            let value = Tracked {
+                id,
+                panic_on_drop: false,
+                dropped: dropped.clone(),
+            };
+            if tbm.insert(&nibbles, masklen, value).is_none() {
+                keys.push((nibbles, masklen));
+            }
+        }
+        (tbm, keys)
+    }
+
+    #[test]
+    fn retain_panic() {
+        for &panic_in_drop in &[false, true] {
+            let dropped = Rc::new(RefCell::new(Vec::new()));
+            let (mut tbm, keys) = tracked_trie(2000, &dropped);
+            let len = tbm.len();
+            dropped.borrow_mut().clear(); // replaced duplicates
+            let mut calls = 0;
+            let ret = panic::catch_unwind(AssertUnwindSafe(|| {
+                tbm.retain(|_, _, value| {
+                    calls += 1;
+                    if calls == len / 2 {
+                        if panic_in_drop {
+                            value.panic_on_drop = true;
+                            return false;
+                        }
+                        panic!("predicate");
+                    }
+                    value.id % 3 != 0
+                })
+            }));
+            assert!(ret.is_err());
+
+            // the trie is consistent and values are dropped exactly once
+            let remaining: Vec<u32> = tbm.iter().map(|(_, _, value)| value.id).collect();
+            assert_eq!(remaining.len(), tbm.len());
+            assert_eq!(remaining.len() + dropped.borrow().len(), len);
+            let found: Vec<u32> = keys
+                .iter()
+                .filter_map(|(nibbles, masklen)| tbm.exact_match(nibbles, *masklen))
+                .map(|value| value.id)
+                .collect();
+            assert_eq!(found.len(), remaining.len());
+            assert!(found.iter().all(|id| remaining.contains(id)));
+            tbm.retain(|_, _, value| value.id % 2 == 0);
+            mem::drop(tbm);
+            let mut dropped = dropped.borrow().clone();
+            dropped.sort();
+            dropped.dedup();
+            assert_eq!(dropped.len(), len);
+        }
+    }
+
+    #[test]
+    fn remove_subtree_panic() {
+        let dropped = Rc::new(RefCell::new(Vec::new()));
+        let (mut tbm, _) = tracked_trie(2000, &dropped);
+        let len = tbm.len();
+        dropped.borrow_mut().clear(); // replaced duplicates
+        let mut subtree = tbm.iter_mut().filter(|(nibbles, _, _)| nibbles[0] >= 0x8);
+        subtree.nth(100).unwrap().2.panic_on_drop = true;
+        let ret = panic::catch_unwind(AssertUnwindSafe(|| tbm.remove_subtree(&[0x8], 1)));
+        assert!(ret.is_err());
+
+        // the trie is consistent and values are dropped exactly once
+        assert_eq!(tbm.iter().count(), tbm.len());
+        assert!(tbm.iter().all(|(nibbles, _, _)| nibbles[0] < 0x8));
+        assert_eq!(tbm.len() + dropped.borrow().len(), len);
+        mem::drop(tbm);
+        let mut dropped = dropped.borrow().clone();
+        dropped.sort();
+        dropped.dedup();
+        assert_eq!(dropped.len(), len);
+    }
+
     struct Thing {
         id: usize,
     }
@@ -654,4 +2396,55 @@ mod tests {
         println!("should drop 3 - 4");
     }
 
+    #[test]
+    fn into_iter_drop_back() {
+        let mut tbm: TreeBitmap<Thing> = TreeBitmap::new();
+        let (nibbles_a, mask_a) = (&[0], 0);
+        let (nibbles_b, mask_b) = (&[0, 10], 8);
+        let (nibbles_c, mask_c) = (&[0, 10, 0, 10, 0, 10], 24);
+        let (nibbles_d, mask_d) = (&[0, 10, 0, 10, 1, 11], 24);
+        tbm.insert(nibbles_a, mask_a, Thing { id: 1 });
+        tbm.insert(nibbles_b, mask_b, Thing { id: 2 });
+        tbm.insert(nibbles_c, mask_c, Thing { id: 3 });
+        tbm.insert(nibbles_d, mask_d, Thing { id: 4 });
+        let mut iter = tbm.into_iter();
+        assert_eq!(iter.next_back().map(|(_, masklen, _)| masklen), Some(24));
+        assert_eq!(iter.next().map(|(_, masklen, _)| masklen), Some(0));
+        assert_eq!(iter.len(), 2);
+        println!("should drop 2 - 3");
+    }
+
+    #[test]
+    fn reserve_insert_remove() {
+        let mut tbm: TreeBitmap<u32> = TreeBitmap::new();
+        let mut keys = Vec::new();
+        let mut seed: u32 = 0x0e5e_0022;
+        for i in 0..5000 {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            // full nibbles fill end nodes, which are pushed down by longer keys
+            let masklen = match i % 3 {
+                0 => (seed >> 28) / 4 * 4,
+                _ => seed % 33,
+            };
+            let bits = if masklen == 0 {
+                0
+            } else {
+                seed & (!0 << (32 - masklen))
+            };
+            let nibbles: Vec<u8> = (0..8).map(|n| (bits >> (28 - n * 4) & 0xf) as u8).collect();
+            tbm.reserve_insert(masklen).unwrap();
+            let mem_usage = tbm.mem_usage();
+            tbm.insert(&nibbles, masklen, i);
+            assert_eq!(tbm.mem_usage(), mem_usage, "insert allocated");
+            keys.push((nibbles, masklen));
+        }
+        for (nibbles, masklen) in keys {
+            tbm.reserve_remove(masklen).unwrap();
+            let mem_usage = tbm.mem_usage();
+            tbm.remove(&nibbles, masklen);
+            assert_eq!(tbm.mem_usage(), mem_usage, "remove allocated");
+        }
+        assert_eq!(tbm.len(), 0);
+    }
+
 }
@@ -0,0 +1,154 @@
+// Copyright 2016 Hroi Sigurdsson
+//
+// Licensed under the MIT license <LICENSE-MIT or http://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed except according to those terms.
+
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+use super::allocator::{Allocator, AllocatorHandle};
+use super::TreeBitmap;
+
+/// A change to the layout of the trie nodes, given by the length and offset
+/// of the node collection before the change.
+pub enum NodeOp {
+    Alloc,
+    Insert(u32, u32, u32), // len, offset, index
+    Remove(u32, u32, u32), // len, offset, index
+    Free(u32),             // offset
+    Release(u32, u32),     // len, offset
+}
+
+/// Location of a trie node: the collection holding it and its index there.
+#[derive(Clone, Copy, Debug)]
+pub struct NodeLocation {
+    len: u32,
+    offset: u32,
+    index: u32,
+}
+
+impl NodeLocation {
+    pub const ROOT: NodeLocation = NodeLocation {
+        len: 1,
+        offset: 0,
+        index: 0,
+    };
+
+    pub fn new(hdl: &AllocatorHandle, index: u32) -> Self {
+        NodeLocation {
+            len: hdl.len,
+            offset: hdl.offset,
+            index,
+        }
+    }
+
+    fn handle(&self) -> AllocatorHandle {
+        AllocatorHandle::generate(self.len, self.offset)
+    }
+}
+
+/// Optional value for each node of a ```TreeBitmap```, looked up by the
+/// location of the node.
+///
+/// Slots are kept in an allocator laid out like the trie nodes, which replays
+/// the node changes logged by the trie in ```sync```. A slot holds the index
+/// of its value plus one, or 0 if the node has no value.
+pub struct NodeData<V> {
+    slots: Allocator<u32>,
+    values: Vec<Option<V>>,
+    free: Vec<u32>,
+}
+
+impl<V> NodeData<V> {
+    /// Returns ```NodeData``` with no values, laid out like a new trie.
+    pub fn new() -> Self {
+        let mut slots = Allocator::new();
+        let mut root_hdl = slots.alloc(0);
+        slots.insert(&mut root_hdl, 0, 0);
+        NodeData {
+            slots,
+            values: Vec::new(),
+            free: Vec::new(),
+        }
+    }
+
+    /// Apply the node changes logged by ```trie``` since the last call.
+    pub fn sync<T>(&mut self, trie: &mut TreeBitmap<T>) {
+        if let Some(ref mut log) = trie.node_log {
+            for op in log.drain(..) {
+                self.apply(op);
+            }
+        }
+    }
+
+    fn apply(&mut self, op: NodeOp) {
+        match op {
+            NodeOp::Alloc => {
+                self.slots.alloc(0);
+            }
+            NodeOp::Insert(len, offset, index) => {
+                let mut hdl = AllocatorHandle::generate(len, offset);
+                self.slots.insert(&mut hdl, index, 0);
+            }
+            NodeOp::Remove(len, offset, index) => {
+                let mut hdl = AllocatorHandle::generate(len, offset);
+                let slot = self.slots.remove(&mut hdl, index);
+                self.free_slot(slot);
+            }
+            NodeOp::Free(offset) => {
+                self.slots.free(&mut AllocatorHandle::generate(0, offset));
+            }
+            NodeOp::Release(len, offset) => {
+                let mut hdl = AllocatorHandle::generate(len, offset);
+                for index in 0..len {
+                    let slot = *self.slots.get(&hdl, index);
+                    self.free_slot(slot);
+                }
+                self.slots.release(&mut hdl);
+            }
+        }
+    }
+
+    fn free_slot(&mut self, slot: u32) {
+        if slot > 0 {
+            self.values[slot as usize - 1] = None;
+            self.free.push(slot);
+        }
+    }
+
+    /// Returns the value of the node at ```location```.
+    pub fn get(&self, location: NodeLocation) -> Option<&V> {
+        match *self.slots.get(&location.handle(), location.index) {
+            0 => None,
+            slot => self.values[slot as usize - 1].as_ref(),
+        }
+    }
+
+    /// Set the value of the node at ```location```.
+    pub fn set(&mut self, location: NodeLocation, value: V) {
+        let hdl = location.handle();
+        match *self.slots.get(&hdl, location.index) {
+            0 => {
+                let slot = match self.free.pop() {
+                    Some(slot) => {
+                        self.values[slot as usize - 1] = Some(value);
+                        slot
+                    }
+                    None => {
+                        self.values.push(Some(value));
+                        self.values.len() as u32
+                    }
+                };
+                self.slots.set(&hdl, location.index, slot);
+            }
+            slot => self.values[slot as usize - 1] = Some(value),
+        }
+    }
+
+    /// Remove the value of the node at ```location```.
+    pub fn unset(&mut self, location: NodeLocation) {
+        let hdl = location.handle();
+        let slot = self.slots.replace(&hdl, location.index, 0);
+        self.free_slot(slot);
+    }
+}
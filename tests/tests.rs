@@ -6,7 +6,8 @@
 
 extern crate treebitmap;
 
-use std::net::{Ipv4Addr, Ipv6Addr};
+use std::collections::BTreeMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::str::FromStr;
 use treebitmap::*;
 
@@ -186,3 +187,1840 @@ fn issue_7() {
 
     println!("len: {}", table.len());
 }
+
+#[test]
+fn matches() {
+    let mut tbl = IpLookupTable::new();
+    tbl.insert(Ipv4Addr::new(0, 0, 0, 0), 0, 1);
+    tbl.insert(Ipv4Addr::new(10, 0, 0, 0), 8, 2);
+    tbl.insert(Ipv4Addr::new(10, 0, 0, 0), 9, 3);
+    tbl.insert(Ipv4Addr::new(10, 1, 0, 0), 16, 4);
+    tbl.insert(Ipv4Addr::new(10, 1, 2, 0), 24, 5);
+    tbl.insert(Ipv4Addr::new(10, 1, 2, 3), 32, 6);
+    tbl.insert(Ipv4Addr::new(10, 128, 0, 0), 9, 7);
+
+    for &lookup_ip in &[
+        Ipv4Addr::new(10, 1, 2, 3),
+        Ipv4Addr::new(10, 1, 2, 4),
+        Ipv4Addr::new(10, 200, 0, 1),
+        Ipv4Addr::new(192, 168, 0, 1),
+    ] {
+        let expected: Vec<_> = (0..33)
+            .filter_map(|masklen| {
+                let ip = Ipv4Addr::from(u32::from(lookup_ip) & (!0u64 << (32 - masklen)) as u32);
+                tbl.exact_match(ip, masklen)
+                    .map(|value| (ip, masklen, value))
+            })
+            .collect();
+        let result: Vec<_> = tbl.matches(lookup_ip).collect();
+        assert_eq!(result, expected);
+        assert_eq!(result.last().cloned(), tbl.longest_match(lookup_ip));
+    }
+
+    let mut tbl6 = IpLookupTable::new();
+    let less_specific = Ipv6Addr::from_str("2001:db8::").unwrap();
+    let more_specific = Ipv6Addr::from_str("2001:db8:dead::").unwrap();
+    tbl6.insert(less_specific, 32, 1);
+    tbl6.insert(more_specific, 48, 2);
+    let lookup_ip = Ipv6Addr::from_str("2001:db8:dead:beef::1").unwrap();
+    let result: Vec<_> = tbl6.matches(lookup_ip).collect();
+    assert_eq!(
+        result,
+        vec![(less_specific, 32, &1), (more_specific, 48, &2)]
+    );
+    assert_eq!(
+        tbl6.matches(Ipv6Addr::from_str("2001::").unwrap()).next(),
+        None
+    );
+}
+
+#[test]
+fn matches_mut() {
+    let mut tbl = IpLookupTable::new();
+    tbl.insert(Ipv4Addr::new(10, 0, 0, 0), 8, 1);
+    tbl.insert(Ipv4Addr::new(10, 1, 0, 0), 16, 2);
+    tbl.insert(Ipv4Addr::new(10, 2, 0, 0), 16, 3);
+
+    for (_ip, _mask, val) in tbl.matches_mut(Ipv4Addr::new(10, 1, 1, 1)) {
+        *val += 10;
+    }
+
+    assert_eq!(tbl.exact_match(Ipv4Addr::new(10, 0, 0, 0), 8), Some(&11));
+    assert_eq!(tbl.exact_match(Ipv4Addr::new(10, 1, 0, 0), 16), Some(&12));
+    assert_eq!(tbl.exact_match(Ipv4Addr::new(10, 2, 0, 0), 16), Some(&3));
+}
+
+#[test]
+fn iter_prefix() {
+    let prefixes = [
+        (Ipv4Addr::new(0, 0, 0, 0), 0),
+        (Ipv4Addr::new(10, 0, 0, 0), 8),
+        (Ipv4Addr::new(10, 0, 0, 0), 9),
+        (Ipv4Addr::new(10, 1, 0, 0), 16),
+        (Ipv4Addr::new(10, 1, 2, 0), 23),
+        (Ipv4Addr::new(10, 1, 2, 0), 24),
+        (Ipv4Addr::new(10, 1, 3, 0), 24),
+        (Ipv4Addr::new(10, 1, 2, 3), 32),
+        (Ipv4Addr::new(10, 128, 0, 0), 9),
+        (Ipv4Addr::new(11, 0, 0, 0), 8),
+        (Ipv4Addr::new(192, 168, 0, 0), 16),
+    ];
+    let mut tbl = IpLookupTable::new();
+    for (i, &(ip, masklen)) in prefixes.iter().enumerate() {
+        tbl.insert(ip, masklen, i);
+    }
+
+    let contains = |outer: (Ipv4Addr, u32), inner: (Ipv4Addr, u32)| {
+        let mask = (!0u64 << (32 - outer.1)) as u32;
+        inner.1 >= outer.1 && u32::from(inner.0) & mask == u32::from(outer.0) & mask
+    };
+    let mut queries = prefixes.to_vec();
+    queries.push((Ipv4Addr::new(10, 1, 0, 0), 20));
+    queries.push((Ipv4Addr::new(10, 1, 2, 0), 30));
+    queries.push((Ipv4Addr::new(10, 1, 2, 2), 31));
+    queries.push((Ipv4Addr::new(12, 0, 0, 0), 8));
+    for &query in &queries {
+        let mut expected: Vec<_> = tbl
+            .iter()
+            .filter(|&(ip, masklen, _)| contains(query, (ip, masklen)))
+            .collect();
+        let mut result: Vec<_> = tbl.iter_prefix(query.0, query.1).collect();
+        expected.sort();
+        result.sort();
+        assert_eq!(result, expected, "iter_prefix({}/{})", query.0, query.1);
+    }
+}
+
+#[test]
+fn iter_prefix_mut() {
+    let mut tbl = IpLookupTable::new();
+    tbl.insert(Ipv6Addr::from_str("2001:db8::").unwrap(), 32, 1);
+    tbl.insert(Ipv6Addr::from_str("2001:db8:100::").unwrap(), 40, 2);
+    tbl.insert(Ipv6Addr::from_str("2001:db8:180::").unwrap(), 41, 3);
+    tbl.insert(Ipv6Addr::from_str("2001:db8:200::").unwrap(), 40, 4);
+
+    let prefix = Ipv6Addr::from_str("2001:db8:100::").unwrap();
+    for (_ip, _mask, val) in tbl.iter_prefix_mut(prefix, 39) {
+        *val += 10;
+    }
+
+    let values: Vec<_> = tbl.iter().map(|(_, _, &val)| val).collect();
+    assert_eq!(values, vec![1, 12, 13, 4]);
+}
+
+#[test]
+fn iter_address_order() {
+    let mut tbl = IpLookupTable::new();
+    let mut seed: u32 = 0x1234_5678;
+    let mut expected = Vec::new();
+    for i in 0..2000 {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        // cluster prefixes so that they nest
+        let ip = Ipv4Addr::from((seed & 0x0f0f_ffff) | 0x0a00_0000);
+        let masklen = (seed >> 27) + (seed >> 31);
+        let ip = Ipv4Addr::from(u32::from(ip) & (!0u64 << (32 - masklen)) as u32);
+        if tbl.insert(ip, masklen, i).is_none() {
+            expected.push((ip, masklen));
+        }
+    }
+    expected.sort();
+
+    let result: Vec<_> = tbl.iter().map(|(ip, masklen, _)| (ip, masklen)).collect();
+    assert_eq!(result, expected);
+    let result: Vec<_> = tbl
+        .into_iter()
+        .map(|(ip, masklen, _)| (ip, masklen))
+        .collect();
+    assert_eq!(result, expected);
+
+    let mut tbl6 = IpLookupTable::new();
+    let prefixes = [
+        ("2001:db8:8000::", 33),
+        ("2001:db8::", 32),
+        ("2001:db8::", 48),
+        ("2001:db8::1", 128),
+        ("2001:db8::", 127),
+        ("2001:db8::", 64),
+        ("2001:db8:1::", 48),
+        ("2001::", 16),
+        ("::", 0),
+    ];
+    for &(ip, masklen) in &prefixes {
+        tbl6.insert(Ipv6Addr::from_str(ip).unwrap(), masklen, ());
+    }
+    let mut expected: Vec<_> = prefixes
+        .iter()
+        .map(|&(ip, masklen)| (Ipv6Addr::from_str(ip).unwrap(), masklen))
+        .collect();
+    expected.sort();
+    let result: Vec<_> = tbl6.iter().map(|(ip, masklen, _)| (ip, masklen)).collect();
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn entry() {
+    let mut tbl = IpLookupTable::new();
+    let prefix = Ipv4Addr::new(10, 0, 0, 0);
+    let more_specific = Ipv4Addr::new(10, 1, 2, 0);
+
+    match tbl.entry(prefix, 8) {
+        Entry::Vacant(entry) => {
+            assert_eq!(entry.key(), (prefix, 8));
+            *entry.insert(String::from("foo")) += "bar";
+        }
+        Entry::Occupied(_) => panic!("expected vacant entry"),
+    }
+    assert_eq!(tbl.len(), 1);
+    assert_eq!(tbl.exact_match(prefix, 8), Some(&String::from("foobar")));
+
+    tbl.entry(more_specific, 24)
+        .and_modify(|_| panic!("entry should be vacant"))
+        .or_insert_with(|| String::from("baz"));
+    tbl.entry(more_specific, 24)
+        .and_modify(|val| val.push('!'))
+        .or_insert_with(|| panic!("entry should be occupied"));
+    assert_eq!(tbl.len(), 2);
+    assert_eq!(
+        tbl.exact_match(more_specific, 24),
+        Some(&String::from("baz!"))
+    );
+
+    match tbl.entry(prefix, 8) {
+        Entry::Occupied(mut entry) => {
+            assert_eq!(entry.key(), (prefix, 8));
+            assert_eq!(entry.get(), "foobar");
+            assert_eq!(entry.insert(String::from("quux")), "foobar");
+            assert_eq!(entry.remove(), "quux");
+        }
+        Entry::Vacant(_) => panic!("expected occupied entry"),
+    }
+    assert_eq!(tbl.len(), 1);
+    assert_eq!(tbl.exact_match(prefix, 8), None);
+    assert_eq!(
+        tbl.longest_match(Ipv4Addr::new(10, 1, 2, 3)),
+        Some((more_specific, 24, &String::from("baz!")))
+    );
+
+    let mut counts: IpLookupTable<Ipv6Addr, u32> = IpLookupTable::new();
+    for ip in &["2001:db8::", "2001:db8::", "2001:db8:1::", "2001:db8::"] {
+        *counts
+            .entry(Ipv6Addr::from_str(ip).unwrap(), 48)
+            .or_default() += 1;
+    }
+    let result: Vec<_> = counts.iter().map(|(_, _, &count)| count).collect();
+    assert_eq!(result, vec![3, 1]);
+}
+
+#[test]
+fn exact_match_mut() {
+    let mut tbl = IpLookupTable::new();
+    tbl.insert(Ipv4Addr::new(10, 0, 0, 0), 8, 1);
+    tbl.insert(Ipv4Addr::new(10, 0, 0, 0), 16, 2);
+    tbl.insert(Ipv4Addr::new(10, 0, 0, 1), 32, 3);
+
+    *tbl.exact_match_mut(Ipv4Addr::new(10, 0, 0, 0), 16).unwrap() += 10;
+    *tbl.exact_match_mut(Ipv4Addr::new(10, 0, 0, 1), 32).unwrap() += 10;
+    assert_eq!(tbl.exact_match_mut(Ipv4Addr::new(10, 0, 0, 0), 24), None);
+
+    assert!(tbl.contains(Ipv4Addr::new(10, 0, 0, 0), 8));
+    assert!(!tbl.contains(Ipv4Addr::new(10, 0, 0, 0), 9));
+    assert_eq!(tbl[(Ipv4Addr::new(10, 0, 0, 0), 8)], 1);
+    assert_eq!(tbl[(Ipv4Addr::new(10, 0, 0, 0), 16)], 12);
+    assert_eq!(tbl[(Ipv4Addr::new(10, 0, 0, 1), 32)], 13);
+}
+
+#[test]
+#[should_panic]
+fn index_missing() {
+    let mut tbl = IpLookupTable::new();
+    tbl.insert(Ipv4Addr::new(10, 0, 0, 0), 8, 1);
+    let _ = tbl[(Ipv4Addr::new(10, 0, 0, 0), 16)];
+}
+
+#[test]
+fn longest_match_mut() {
+    let mut tbl = IpLookupTable::new();
+    let less_specific = Ipv6Addr::from_str("2001:db8::").unwrap();
+    let more_specific = Ipv6Addr::from_str("2001:db8:dead::").unwrap();
+    tbl.insert(less_specific, 32, 0);
+    tbl.insert(more_specific, 48, 0);
+
+    for ip in &["2001:db8:dead::1", "2001:db8:beef::1", "2001:db8:dead::2"] {
+        let (_, _, hits) = tbl
+            .longest_match_mut(Ipv6Addr::from_str(ip).unwrap())
+            .unwrap();
+        *hits += 1;
+    }
+    let result = tbl.longest_match_mut(Ipv6Addr::from_str("2001:db8:dead::3").unwrap());
+    assert_eq!(result, Some((more_specific, 48, &mut 2)));
+    assert_eq!(tbl.exact_match(less_specific, 32), Some(&1));
+    assert_eq!(
+        tbl.longest_match_mut(Ipv6Addr::from_str("2001::").unwrap()),
+        None
+    );
+}
+
+#[test]
+fn retain() {
+    let mut tbl = IpLookupTable::new();
+    let mut seed: u32 = 0x8765_4321;
+    let mut expected = BTreeMap::new();
+    for i in 0..2000u32 {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        let masklen = (seed >> 27) + (seed >> 31);
+        let ip =
+            Ipv4Addr::from(((seed & 0x0f0f_ffff) | 0x0a00_0000) & (!0u64 << (32 - masklen)) as u32);
+        tbl.insert(ip, masklen, i);
+        expected.insert((ip, masklen), i);
+    }
+
+    let mut visited = 0;
+    tbl.retain(|ip, masklen, val| {
+        visited += 1;
+        *val += 1;
+        masklen % 3 != 0 && u32::from(ip) & 0x100 == 0
+    });
+    assert_eq!(visited, expected.len());
+
+    let expected: Vec<_> = expected
+        .into_iter()
+        .filter(|&((ip, masklen), _)| masklen % 3 != 0 && u32::from(ip) & 0x100 == 0)
+        .map(|((ip, masklen), val)| (ip, masklen, val))
+        .collect();
+    assert_eq!(tbl.len(), expected.len());
+    let result: Vec<_> = tbl
+        .iter()
+        .map(|(ip, masklen, &val)| (ip, masklen, val - 1))
+        .collect();
+    assert_eq!(result, expected);
+    for &(ip, masklen, val) in &expected {
+        assert_eq!(tbl.exact_match(ip, masklen), Some(&(val + 1)));
+    }
+
+    tbl.retain(|_, _, _| false);
+    assert_eq!(tbl.len(), 0);
+    assert_eq!(tbl.iter().next(), None);
+    for &(ip, masklen, val) in &expected {
+        tbl.insert(ip, masklen, val);
+    }
+    let result: Vec<_> = tbl
+        .iter()
+        .map(|(ip, masklen, &val)| (ip, masklen, val))
+        .collect();
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn remove_subtree() {
+    let mut tbl = IpLookupTable::new();
+    let mut seed: u32 = 0xdead_beef;
+    let mut expected = BTreeMap::new();
+    for i in 0..2000u32 {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        let masklen = (seed >> 27) + (seed >> 31);
+        let ip =
+            Ipv4Addr::from(((seed & 0x0f0f_ffff) | 0x0a00_0000) & (!0u64 << (32 - masklen)) as u32);
+        tbl.insert(ip, masklen, i);
+        expected.insert((ip, masklen), i);
+    }
+
+    let contains = |outer: (Ipv4Addr, u32), inner: (Ipv4Addr, u32)| {
+        let mask = (!0u64 << (32 - outer.1)) as u32;
+        inner.1 >= outer.1 && u32::from(inner.0) & mask == u32::from(outer.0) & mask
+    };
+    let queries = [
+        (Ipv4Addr::new(10, 3, 0, 0), 16),
+        (Ipv4Addr::new(10, 4, 0, 0), 14),
+        (Ipv4Addr::new(11, 2, 128, 0), 17),
+        (Ipv4Addr::new(11, 5, 0, 0), 20),
+        (Ipv4Addr::new(14, 0, 0, 0), 7),
+        (Ipv4Addr::new(10, 8, 154, 106), 32),
+        (Ipv4Addr::new(10, 8, 154, 106), 31),
+        (Ipv4Addr::new(192, 168, 0, 0), 16),
+    ];
+    for (i, &query) in queries.iter().enumerate() {
+        let removed: Vec<_> = expected
+            .keys()
+            .filter(|&&prefix| contains(query, prefix))
+            .cloned()
+            .collect();
+        let removed: Vec<_> = removed
+            .into_iter()
+            .map(|prefix| {
+                let val = expected.remove(&prefix).unwrap();
+                (prefix.0, prefix.1, val)
+            })
+            .collect();
+        if i % 2 == 0 {
+            assert_eq!(tbl.remove_subtree(query.0, query.1), removed.len());
+        } else {
+            let result: Vec<_> = tbl.drain_prefix(query.0, query.1).collect();
+            assert_eq!(result, removed);
+        }
+        assert_eq!(tbl.len(), expected.len());
+        assert_eq!(tbl.iter_prefix(query.0, query.1).next(), None);
+        let result: Vec<_> = tbl
+            .iter()
+            .map(|(ip, masklen, &val)| ((ip, masklen), val))
+            .collect();
+        assert_eq!(result, expected.clone().into_iter().collect::<Vec<_>>());
+    }
+
+    assert_eq!(
+        tbl.remove_subtree(Ipv4Addr::new(0, 0, 0, 0), 0),
+        expected.len()
+    );
+    assert_eq!(tbl.len(), 0);
+    assert_eq!(tbl.iter().next(), None);
+    for (&(ip, masklen), &val) in &expected {
+        tbl.insert(ip, masklen, val);
+    }
+    for (&(ip, masklen), val) in &expected {
+        assert_eq!(tbl.exact_match(ip, masklen), Some(val));
+    }
+}
+
+#[test]
+fn drain_prefix_drop() {
+    use std::rc::Rc;
+
+    let value = Rc::new(());
+    let mut tbl = IpLookupTable::new();
+    tbl.insert(Ipv6Addr::from_str("2001:db8::").unwrap(), 32, value.clone());
+    tbl.insert(Ipv6Addr::from_str("2001:db8::").unwrap(), 48, value.clone());
+    tbl.insert(
+        Ipv6Addr::from_str("2001:db8:1::").unwrap(),
+        48,
+        value.clone(),
+    );
+    tbl.insert(Ipv6Addr::from_str("2001:db9::").unwrap(), 32, value.clone());
+    assert_eq!(Rc::strong_count(&value), 5);
+
+    let mut drain = tbl.drain_prefix(Ipv6Addr::from_str("2001:db8::").unwrap(), 32);
+    drain.next();
+    drop(drain);
+    assert_eq!(Rc::strong_count(&value), 2);
+    assert_eq!(
+        tbl.remove_subtree(Ipv6Addr::from_str("2001::").unwrap(), 16),
+        1
+    );
+    assert_eq!(Rc::strong_count(&value), 1);
+}
+
+#[test]
+fn clone_eq_hash() {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+    use std::rc::Rc;
+
+    fn hash_of<T: Hash>(t: &T) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        t.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    let mut prefixes = Vec::new();
+    let mut seed: u32 = 0x1234_5678;
+    for i in 0..500u32 {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        let masklen = (seed >> 27) + (seed >> 31);
+        let ip = Ipv4Addr::from(seed & (!0u64 << (32 - masklen)) as u32);
+        prefixes.push((ip, masklen, format!("value {}", i)));
+    }
+
+    let mut tbl = IpLookupTable::new();
+    for &(ip, masklen, ref value) in &prefixes {
+        tbl.insert(ip, masklen, value.clone());
+    }
+    let cloned = tbl.clone();
+    assert_eq!(cloned.len(), tbl.len());
+    assert_eq!(
+        cloned.iter().collect::<Vec<_>>(),
+        tbl.iter().collect::<Vec<_>>()
+    );
+    assert_eq!(cloned, tbl);
+    assert_eq!(hash_of(&cloned), hash_of(&tbl));
+
+    // same contents inserted in a different order, with a detour
+    let mut reversed = IpLookupTable::new();
+    reversed.insert(Ipv4Addr::new(127, 0, 0, 1), 32, "gone".to_string());
+    for &(ip, masklen, ref value) in prefixes.iter().rev() {
+        reversed.insert(ip, masklen, value.clone());
+    }
+    if tbl.exact_match(Ipv4Addr::new(127, 0, 0, 1), 32).is_none() {
+        reversed.remove(Ipv4Addr::new(127, 0, 0, 1), 32);
+    }
+    // later inserts of duplicate prefixes win, so redo those in order
+    for &(ip, masklen, ref value) in &prefixes {
+        reversed.insert(ip, masklen, value.clone());
+    }
+    assert_eq!(reversed, tbl);
+    assert_eq!(hash_of(&reversed), hash_of(&tbl));
+
+    // removing a prefix does not undo the push down it caused
+    let mut a = IpLookupTable::new();
+    a.insert(Ipv4Addr::new(10, 0, 0, 0), 8, ());
+    let mut b = a.clone();
+    b.insert(Ipv4Addr::new(10, 16, 0, 0), 12, ());
+    b.remove(Ipv4Addr::new(10, 16, 0, 0), 12);
+    assert_eq!(format!("{:?}", a), format!("{:?}", b));
+    assert_eq!(a, b);
+    assert_eq!(hash_of(&a), hash_of(&b));
+
+    // the clone is independent of the original
+    let mut cloned = cloned;
+    let (ip, masklen, _) = prefixes[0];
+    let original = tbl.exact_match(ip, masklen).unwrap().clone();
+    *cloned.exact_match_mut(ip, masklen).unwrap() = "changed".to_string();
+    assert_ne!(cloned, tbl);
+    assert_eq!(tbl.exact_match(ip, masklen), Some(&original));
+    cloned.insert(ip, masklen, original);
+    assert_eq!(cloned, tbl);
+    cloned.insert(Ipv4Addr::new(0, 0, 0, 0), 0, "default".to_string());
+    assert_ne!(cloned, tbl);
+
+    // values are cloned, not copied bitwise
+    let value = Rc::new(());
+    let mut tbl = IpLookupTable::new();
+    tbl.insert(Ipv6Addr::from_str("2001:db8::").unwrap(), 32, value.clone());
+    tbl.insert(Ipv6Addr::from_str("2001:db8::").unwrap(), 64, value.clone());
+    let cloned = tbl.clone();
+    assert_eq!(Rc::strong_count(&value), 5);
+    drop(tbl);
+    assert_eq!(Rc::strong_count(&value), 3);
+    drop(cloned);
+    assert_eq!(Rc::strong_count(&value), 1);
+}
+
+#[test]
+fn debug() {
+    let mut tbl = IpLookupTable::new();
+    assert_eq!(format!("{:?}", tbl), "{}");
+    tbl.insert(Ipv4Addr::new(10, 1, 0, 0), 16, 2);
+    tbl.insert(Ipv4Addr::new(10, 0, 0, 0), 8, 1);
+    tbl.insert(Ipv4Addr::new(0, 0, 0, 0), 0, 0);
+    assert_eq!(
+        format!("{:?}", tbl),
+        "{0.0.0.0/0: 0, 10.0.0.0/8: 1, 10.1.0.0/16: 2}"
+    );
+}
+
+#[test]
+fn longest_match_prefix_parent_children() {
+    let mut tbl = IpLookupTable::new();
+    let mut expected = BTreeMap::new();
+    let mut seed: u32 = 0x0bad_cafe;
+    let mut random_prefix = || {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        let masklen = (seed >> 27) + (seed >> 31);
+        let ip = (seed & 0x00ff_ffff) | 0x0a00_0000;
+        (
+            Ipv4Addr::from(ip & (!0u64 << (32 - masklen)) as u32),
+            masklen,
+        )
+    };
+    for i in 0..1000u32 {
+        let (ip, masklen) = random_prefix();
+        tbl.insert(ip, masklen, i);
+        expected.insert((ip, masklen), i);
+    }
+
+    let contains = |outer: (Ipv4Addr, u32), inner: (Ipv4Addr, u32)| {
+        let mask = (!0u64 << (32 - outer.1)) as u32;
+        inner.1 >= outer.1 && u32::from(inner.0) & mask == u32::from(outer.0) & mask
+    };
+    let mut queries: Vec<_> = expected.keys().cloned().collect();
+    for _ in 0..1000 {
+        queries.push(random_prefix());
+    }
+    queries.push((Ipv4Addr::new(0, 0, 0, 0), 0));
+    queries.push((Ipv4Addr::new(10, 0, 0, 0), 8));
+    queries.push((Ipv4Addr::new(192, 168, 1, 0), 24));
+
+    for &query in &queries {
+        let covering = expected
+            .iter()
+            .filter(|&(&prefix, _)| contains(prefix, query))
+            .max_by_key(|&(&(_, masklen), _)| masklen)
+            .map(|(&(ip, masklen), value)| (ip, masklen, value));
+        assert_eq!(tbl.longest_match_prefix(query.0, query.1), covering);
+
+        let parent = expected
+            .iter()
+            .filter(|&(&prefix, _)| prefix.1 < query.1 && contains(prefix, query))
+            .max_by_key(|&(&(_, masklen), _)| masklen)
+            .map(|(&(ip, masklen), value)| (ip, masklen, value));
+        assert_eq!(tbl.parent(query.0, query.1), parent);
+
+        let below: Vec<_> = expected
+            .keys()
+            .filter(|&&prefix| prefix.1 > query.1 && contains(query, prefix))
+            .collect();
+        let children: Vec<_> = below
+            .iter()
+            .filter(|&&&prefix| {
+                !below
+                    .iter()
+                    .any(|&&other| other != prefix && contains(other, prefix))
+            })
+            .map(|&&(ip, masklen)| (ip, masklen, &expected[&(ip, masklen)]))
+            .collect();
+        assert_eq!(tbl.children(query.0, query.1).collect::<Vec<_>>(), children);
+    }
+}
+
+#[test]
+fn shortest_match() {
+    let mut tbl = IpLookupTable::new();
+    let mut expected = BTreeMap::new();
+    let mut seed: u32 = 0x5eed_1234;
+    for i in 0..1000u32 {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        let masklen = (seed >> 27) + (seed >> 31);
+        let ip = (seed & 0x0fff_ffff) | 0x1000_0000;
+        let ip = Ipv4Addr::from(ip & (!0u64 << (32 - masklen)) as u32);
+        tbl.insert(ip, masklen, i);
+        expected.insert((ip, masklen), i);
+    }
+
+    for _ in 0..5000 {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        let ip = Ipv4Addr::from(seed ^ (seed << 13));
+        let shortest = expected
+            .iter()
+            .filter(|&(&(prefix, masklen), _)| {
+                let mask = (!0u64 << (32 - masklen)) as u32;
+                u32::from(ip) & mask == u32::from(prefix)
+            })
+            .min_by_key(|&(&(_, masklen), _)| masklen)
+            .map(|(&(prefix, masklen), value)| (prefix, masklen, value));
+        assert_eq!(tbl.shortest_match(ip), shortest);
+        assert_eq!(tbl.shortest_match(ip), tbl.matches(ip).next());
+    }
+}
+
+#[test]
+fn ordered_navigation() {
+    use std::ops::Bound::{Excluded, Unbounded};
+
+    let mut tbl = IpLookupTable::new();
+    let mut expected = BTreeMap::new();
+    let mut seed: u32 = 0xfeed_f00d;
+    let mut random_prefix = || {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        let masklen = (seed >> 27) + (seed >> 31);
+        let ip = (seed & 0x00ff_ffff) | 0x0a00_0000;
+        (
+            Ipv4Addr::from(ip & (!0u64 << (32 - masklen)) as u32),
+            masklen,
+        )
+    };
+    assert_eq!(tbl.first(), None);
+    assert_eq!(tbl.last(), None);
+    assert_eq!(tbl.next_after(Ipv4Addr::new(0, 0, 0, 0), 0), None);
+    assert_eq!(tbl.prev_before(Ipv4Addr::new(255, 255, 255, 255), 32), None);
+    for i in 0..1000u32 {
+        let (ip, masklen) = random_prefix();
+        tbl.insert(ip, masklen, i);
+        expected.insert((ip, masklen), i);
+    }
+
+    let entry = |(&(ip, masklen), value)| (ip, masklen, value);
+    assert_eq!(tbl.first(), expected.iter().next().map(entry));
+    assert_eq!(tbl.last(), expected.iter().next_back().map(entry));
+
+    let mut queries: Vec<_> = expected.keys().cloned().collect();
+    for _ in 0..2000 {
+        queries.push(random_prefix());
+    }
+    queries.push((Ipv4Addr::new(0, 0, 0, 0), 0));
+    queries.push((Ipv4Addr::new(10, 0, 0, 0), 8));
+    queries.push((Ipv4Addr::new(255, 255, 255, 255), 32));
+    for &query in &queries {
+        let next = expected
+            .range((Excluded(query), Unbounded))
+            .next()
+            .map(entry);
+        assert_eq!(tbl.next_after(query.0, query.1), next, "{:?}", query);
+        let prev = expected
+            .range((Unbounded, Excluded(query)))
+            .next_back()
+            .map(entry);
+        assert_eq!(tbl.prev_before(query.0, query.1), prev, "{:?}", query);
+    }
+
+    // walk the table in both directions
+    let mut cur = tbl.first().map(|(ip, masklen, _)| (ip, masklen));
+    let mut forward = Vec::new();
+    while let Some((ip, masklen)) = cur {
+        forward.push((ip, masklen));
+        cur = tbl
+            .next_after(ip, masklen)
+            .map(|(ip, masklen, _)| (ip, masklen));
+    }
+    assert_eq!(forward, expected.keys().cloned().collect::<Vec<_>>());
+    let mut cur = tbl.last().map(|(ip, masklen, _)| (ip, masklen));
+    let mut backward = Vec::new();
+    while let Some((ip, masklen)) = cur {
+        backward.push((ip, masklen));
+        cur = tbl
+            .prev_before(ip, masklen)
+            .map(|(ip, masklen, _)| (ip, masklen));
+    }
+    forward.reverse();
+    assert_eq!(backward, forward);
+
+    let mut popped = Vec::new();
+    while let Some(item) = tbl.pop_first() {
+        popped.push(item);
+    }
+    assert_eq!(tbl.len(), 0);
+    assert_eq!(
+        popped,
+        expected
+            .into_iter()
+            .map(|((ip, masklen), value)| (ip, masklen, value))
+            .collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn iter_from_cursor() {
+    use std::ops::Bound::{Included, Unbounded};
+
+    let mut tbl = IpLookupTable::new();
+    let mut expected = BTreeMap::new();
+    let mut seed: u32 = 0xc0de_cafe;
+    let mut random_prefix = || {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        let masklen = (seed >> 26) + 64;
+        let ip = Ipv6Addr::new(0x2001, 0xdb8, (seed >> 16) as u16, seed as u16, 0, 0, 0, 0);
+        (treebitmap::address::Address::mask(ip, masklen), masklen)
+    };
+    for i in 0..1000u32 {
+        let (ip, masklen) = random_prefix();
+        tbl.insert(ip, masklen, i);
+        expected.insert((ip, masklen), i);
+    }
+
+    for _ in 0..200 {
+        let query = random_prefix();
+        let result: Vec<_> = tbl.iter_from(query.0, query.1).collect();
+        let from: Vec<_> = expected
+            .range((Included(query), Unbounded))
+            .map(|(&(ip, masklen), value)| (ip, masklen, value))
+            .collect();
+        assert_eq!(result, from);
+    }
+
+    // page through the table, modifying it between pages
+    let mut cursor = tbl.iter().cursor();
+    let mut paged = Vec::new();
+    loop {
+        let page: Vec<_> = {
+            let mut iter = tbl.iter_from_cursor(cursor);
+            let page: Vec<_> = iter
+                .by_ref()
+                .take(64)
+                .map(|(ip, masklen, &value)| (ip, masklen, value))
+                .collect();
+            cursor = iter.cursor();
+            page
+        };
+        if page.is_empty() {
+            break;
+        }
+        let &(ip, masklen, _) = page.last().unwrap();
+        if let Some((next_ip, next_masklen, _)) = tbl.next_after(ip, masklen) {
+            // entries behind the cursor do not show up, entries ahead do
+            tbl.remove(next_ip, next_masklen);
+            expected.remove(&(next_ip, next_masklen));
+            tbl.insert(ip, masklen, 0);
+        }
+        paged.extend(page);
+    }
+    assert_eq!(paged.len(), expected.len());
+    let expected: Vec<_> = expected
+        .into_iter()
+        .map(|((ip, masklen), value)| (ip, masklen, value))
+        .collect();
+    assert_eq!(paged, expected);
+    assert_eq!(
+        cursor,
+        IterCursor::after(
+            expected[expected.len() - 1].0,
+            expected[expected.len() - 1].1
+        )
+    );
+    assert_eq!(tbl.iter_from_cursor(cursor).next(), None);
+
+    // the cursor only follows next, so resuming returns the back entries again
+    let all: Vec<_> = tbl.iter().map(|(ip, masklen, _)| (ip, masklen)).collect();
+    let mut iter = tbl.iter();
+    iter.nth(9);
+    iter.nth_back(9);
+    let cursor = iter.cursor();
+    assert_eq!(cursor, IterCursor::after(all[9].0, all[9].1));
+    let resumed: Vec<_> = tbl
+        .iter_from_cursor(cursor)
+        .map(|(ip, masklen, _)| (ip, masklen))
+        .collect();
+    assert_eq!(resumed, &all[10..]);
+    let mut iter = tbl.iter_mut();
+    assert_eq!(iter.cursor(), IterCursor::at("::".parse().unwrap(), 0));
+    iter.nth(9);
+    iter.nth_back(9);
+    assert_eq!(iter.cursor(), cursor);
+}
+
+#[test]
+fn double_ended_iter() {
+    let mut tbl = IpLookupTable::new();
+    let mut expected = BTreeMap::new();
+    let mut seed: u32 = 0xabad_1dea;
+    for i in 0..1000u32 {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        let masklen = (seed >> 27) + (seed >> 31);
+        let ip = Ipv4Addr::from(seed & (!0u64 << (32 - masklen)) as u32);
+        tbl.insert(ip, masklen, i);
+        expected.insert((ip, masklen), i);
+    }
+    let expected: Vec<_> = expected
+        .into_iter()
+        .map(|((ip, masklen), value)| (ip, masklen, value))
+        .collect();
+
+    assert_eq!(tbl.iter().len(), expected.len());
+    let result: Vec<_> = tbl
+        .iter()
+        .rev()
+        .map(|(ip, masklen, &v)| (ip, masklen, v))
+        .collect();
+    assert_eq!(result, expected.iter().rev().cloned().collect::<Vec<_>>());
+    assert_eq!(
+        tbl.iter()
+            .nth_back(10)
+            .map(|(ip, masklen, &v)| (ip, masklen, v)),
+        Some(expected[expected.len() - 11])
+    );
+
+    // alternate between both ends until they meet
+    let mut iter = tbl.iter();
+    let (mut front, mut back) = (Vec::new(), Vec::new());
+    for i in 0.. {
+        assert_eq!(iter.len(), expected.len() - i);
+        let item = if i % 3 == 0 {
+            iter.next_back()
+        } else {
+            iter.next()
+        };
+        match item {
+            Some((ip, masklen, &v)) if i % 3 == 0 => back.push((ip, masklen, v)),
+            Some((ip, masklen, &v)) => front.push((ip, masklen, v)),
+            None => break,
+        }
+    }
+    assert_eq!(iter.next(), None);
+    assert_eq!(iter.next_back(), None);
+    back.reverse();
+    front.extend(back);
+    assert_eq!(front, expected);
+
+    let mut iter = tbl.iter_mut();
+    while let (Some(a), Some(b)) = (iter.next(), iter.next_back()) {
+        *a.2 += 1;
+        *b.2 += 1;
+    }
+    drop(iter);
+    for (ip, masklen, &value) in tbl.iter() {
+        let i = expected
+            .binary_search_by_key(&(ip, masklen), |e| (e.0, e.1))
+            .unwrap();
+        let incremented = expected.len() % 2 == 0 || i != expected.len() / 2;
+        assert_eq!(value, expected[i].2 + incremented as u32);
+    }
+
+    let mut into_iter = tbl.into_iter();
+    assert_eq!(into_iter.len(), expected.len());
+    let last = into_iter.next_back().unwrap();
+    assert_eq!(
+        (last.0, last.1),
+        (
+            expected[expected.len() - 1].0,
+            expected[expected.len() - 1].1
+        )
+    );
+    assert_eq!(into_iter.len(), expected.len() - 1);
+    let result: Vec<_> = into_iter
+        .rev()
+        .map(|(ip, masklen, _)| (ip, masklen))
+        .collect();
+    assert_eq!(
+        result,
+        expected[..expected.len() - 1]
+            .iter()
+            .rev()
+            .map(|e| (e.0, e.1))
+            .collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn trie_cursor() {
+    use treebitmap::address::Address;
+
+    fn walk<T>(cursor: &mut TrieCursor<T>, found: &mut Vec<(Vec<u8>, u32, T)>)
+    where
+        T: Copy,
+    {
+        for (nibble, masklen, &value) in cursor.results() {
+            let mut nibbles = cursor.nibbles().to_vec();
+            nibbles.push(nibble);
+            found.push((nibbles, masklen, value));
+        }
+        let children: Vec<_> = cursor.children().collect();
+        assert_eq!(children.len() as u32, cursor.child_count());
+        for nibble in 0..16 {
+            assert_eq!(cursor.has_child(nibble), children.contains(&nibble));
+        }
+        for nibble in children {
+            let depth = cursor.depth();
+            assert!(cursor.descend(nibble));
+            assert_eq!(cursor.depth(), depth + 1);
+            walk(cursor, found);
+            assert!(cursor.ascend());
+        }
+    }
+
+    let mut tbl = IpLookupTable::new();
+    let mut seed: u32 = 0x7e57_7e57;
+    for i in 0..1000u32 {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        let masklen = (seed >> 27) + (seed >> 31);
+        let ip = Ipv4Addr::from(seed & (!0u64 << (32 - masklen)) as u32);
+        tbl.insert(ip, masklen, i);
+    }
+
+    let mut cursor = tbl.trie_cursor();
+    let mut found = Vec::new();
+    walk(&mut cursor, &mut found);
+    assert_eq!(cursor.depth(), 0);
+    assert!(!cursor.ascend());
+    assert!(!cursor.descend(16));
+
+    let mut found: Vec<_> = found
+        .into_iter()
+        .map(|(nibbles, masklen, value)| (Ipv4Addr::from_nibbles(&nibbles), masklen, value))
+        .collect();
+    found.sort();
+    let expected: Vec<_> = tbl
+        .iter()
+        .map(|(ip, masklen, &v)| (ip, masklen, v))
+        .collect();
+    assert_eq!(found, expected);
+}
+
+#[test]
+fn bit_string_keys() {
+    use treebitmap::tree_bitmap::TreeBitmap;
+
+    fn padded(nibbles: &[u8]) -> Vec<u8> {
+        let mut ret = nibbles.to_vec();
+        ret.resize(10, 0);
+        ret
+    }
+
+    let mut tbm = TreeBitmap::new();
+    let mut control = BTreeMap::new();
+    let mut seed: u32 = 0xb175_0001;
+    for i in 0..2000u32 {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        let masklen = seed % 41;
+        // keys only hold the nibbles needed for masklen
+        let nibbles: Vec<u8> = (0..(masklen + 3) / 4)
+            .map(|n| ((seed.rotate_left(n * 4) ^ i) & 0xf) as u8)
+            .collect();
+        let nibbles = masked(&nibbles, masklen);
+        assert_eq!(
+            tbm.insert(&nibbles, masklen, i).is_some(),
+            control.contains_key(&(nibbles.clone(), masklen))
+        );
+        control.insert((nibbles, masklen), i);
+    }
+    assert_eq!(tbm.len(), control.len());
+    assert!(!tbm.is_empty());
+
+    for ((nibbles, masklen), value) in &control {
+        assert_eq!(tbm.exact_match(nibbles, *masklen), Some(value));
+    }
+
+    for _ in 0..2000 {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        let len = (seed % 11) as usize;
+        let key: Vec<u8> = (0..len as u32)
+            .map(|n| (seed.rotate_left(n * 4 + 3) & 0xf) as u8)
+            .collect();
+        let expected = (0..=(len as u32 * 4))
+            .rev()
+            .filter_map(|masklen| {
+                control
+                    .get(&(masked(&key, masklen), masklen))
+                    .map(|v| (masklen, v))
+            })
+            .next();
+        assert_eq!(tbm.longest_match(&key), expected);
+    }
+
+    let mut expected: Vec<_> = control
+        .iter()
+        .map(|((nibbles, masklen), v)| (padded(nibbles), *masklen, *v))
+        .collect();
+    expected.sort();
+    let found: Vec<_> = tbm
+        .iter()
+        .map(|(nibbles, masklen, v)| (padded(&nibbles), masklen, *v))
+        .collect();
+    assert_eq!(found, expected);
+
+    for ((nibbles, masklen), value) in &control {
+        assert_eq!(tbm.remove(nibbles, *masklen), Some(*value));
+    }
+    assert!(tbm.is_empty());
+    assert_eq!(tbm.iter().next(), None);
+}
+
+// the nibbles needed for masklen, with the bits past masklen cleared
+fn masked(nibbles: &[u8], masklen: u32) -> Vec<u8> {
+    nibbles
+        .iter()
+        .take((masklen as usize + 3) / 4)
+        .enumerate()
+        .map(|(i, nibble)| {
+            let bits = masklen.saturating_sub(i as u32 * 4).min(4);
+            nibble & !(0xf >> bits)
+        })
+        .collect()
+}
+
+#[test]
+#[should_panic]
+fn bit_string_key_too_short() {
+    let mut tbm = treebitmap::tree_bitmap::TreeBitmap::new();
+    tbm.insert(&[1, 2], 9, ());
+}
+
+#[test]
+#[should_panic(expected = "key has bits set past masklen")]
+fn bit_string_key_host_bits_set() {
+    let mut tbm = treebitmap::tree_bitmap::TreeBitmap::new();
+    tbm.insert(&[1, 3], 7, ());
+}
+
+#[test]
+#[should_panic]
+fn bit_string_key_not_nibbles() {
+    let tbm: treebitmap::tree_bitmap::TreeBitmap<()> = Default::default();
+    tbm.longest_match(&[1, 16]);
+}
+
+#[test]
+fn longest_match_where() {
+    let mut tbl = IpLookupTable::new();
+    let mut control = BTreeMap::new();
+    let mut seed: u32 = 0x1f00_d00d;
+    for i in 0..2000u32 {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        let masklen = (seed >> 27) + (seed >> 31);
+        let ip = Ipv4Addr::from(seed & (!0u64 << (32 - masklen)) as u32);
+        tbl.insert(ip, masklen, i);
+        control.insert((ip, masklen), i);
+    }
+
+    for _ in 0..2000 {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        let lookup = Ipv4Addr::from(seed);
+        let expected = (0..=32)
+            .rev()
+            .filter_map(|masklen| {
+                let ip = treebitmap::address::Address::mask(lookup, masklen);
+                control.get(&(ip, masklen)).map(|v| (ip, masklen, v))
+            })
+            .find(|&(_, _, v)| v % 3 == 0);
+        let mut calls = 0;
+        let found = tbl.longest_match_where(lookup, |_, v| {
+            calls += 1;
+            v % 3 == 0
+        });
+        assert_eq!(found, expected);
+        assert!(calls <= tbl.matches(lookup).count());
+        assert_eq!(
+            tbl.longest_match_where(lookup, |_, _| true),
+            tbl.longest_match(lookup)
+        );
+    }
+}
+
+#[test]
+fn longest_match_within() {
+    let mut tbl = IpLookupTable::new();
+    let mut control = BTreeMap::new();
+    let mut seed: u32 = 0x0b0b_cafe;
+    for i in 0..2000u32 {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        let masklen = (seed >> 27) + (seed >> 31);
+        let ip = Ipv4Addr::from(seed & (!0u64 << (32 - masklen)) as u32);
+        tbl.insert(ip, masklen, i);
+        control.insert((ip, masklen), i);
+    }
+
+    for _ in 0..2000 {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        let lookup = Ipv4Addr::from(seed);
+        let min_len = seed % 33;
+        let max_len = (seed >> 8) % 40;
+        let expected = (min_len..=max_len.min(32))
+            .rev()
+            .filter_map(|masklen| {
+                let ip = treebitmap::address::Address::mask(lookup, masklen);
+                control.get(&(ip, masklen)).map(|v| (ip, masklen, v))
+            })
+            .next();
+        assert_eq!(tbl.longest_match_within(lookup, min_len, max_len), expected);
+    }
+}
+
+#[test]
+fn aggregate_under() {
+    use treebitmap::address::Address;
+
+    struct Min;
+
+    impl Aggregate<u32> for Min {
+        type Output = Option<u32>;
+
+        fn empty(&self) -> Option<u32> {
+            None
+        }
+
+        fn single(&self, value: &u32) -> Option<u32> {
+            Some(*value)
+        }
+
+        fn combine(&self, a: &Option<u32>, b: &Option<u32>) -> Option<u32> {
+            match (*a, *b) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            }
+        }
+    }
+
+    fn check(
+        counts: &AggregateTable<Ipv4Addr, u32, Count>,
+        mins: &AggregateTable<Ipv4Addr, u32, Min>,
+        control: &BTreeMap<(Ipv4Addr, u32), u32>,
+        ip: Ipv4Addr,
+        masklen: u32,
+    ) {
+        let under: Vec<u32> = control
+            .iter()
+            .filter(|&(&(p, l), _)| l >= masklen && p.mask(masklen) == ip.mask(masklen))
+            .map(|(_, &v)| v)
+            .collect();
+        assert_eq!(counts.aggregate_under(ip, masklen), under.len());
+        assert_eq!(
+            mins.aggregate_under(ip, masklen),
+            under.iter().cloned().min()
+        );
+    }
+
+    let mut counts = AggregateTable::new(Count);
+    let mut mins = AggregateTable::new(Min);
+    let mut control = BTreeMap::new();
+    let mut seed: u32 = 0xa99e_6a7e;
+    for i in 0..4000u32 {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        // keep prefixes within 10.0.0.0/8 so the aggregates overlap
+        let masklen = 8 + seed % 25;
+        let ip =
+            Ipv4Addr::from((seed & 0x00ff_ffff | 0x0a00_0000) & (!0u64 << (32 - masklen)) as u32);
+        if i % 3 == 2 {
+            let (ip, masklen) = match control.keys().nth(seed as usize % control.len()) {
+                Some(&key) => key,
+                None => unreachable!(),
+            };
+            let expected = control.remove(&(ip, masklen));
+            assert_eq!(counts.remove(ip, masklen), expected);
+            assert_eq!(mins.remove(ip, masklen), expected);
+        } else {
+            assert_eq!(
+                counts.insert(ip, masklen, i),
+                control.insert((ip, masklen), i)
+            );
+            mins.insert(ip, masklen, i);
+        }
+        if i % 20 == 0 {
+            check(&counts, &mins, &control, ip, seed % 33);
+        }
+    }
+    assert_eq!(counts.len(), control.len());
+
+    for &(ip, masklen) in control.keys().take(200) {
+        for l in 0..=masklen {
+            check(&counts, &mins, &control, ip, l);
+        }
+    }
+
+    // remove whole subtrees
+    for _ in 0..50 {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        let masklen = 12 + seed % 13;
+        let ip = Ipv4Addr::from(seed & 0x00ff_ffff | 0x0a00_0000).mask(masklen);
+        let before = control.len();
+        control.retain(|&(p, l), _| l < masklen || p.mask(masklen) != ip);
+        assert_eq!(counts.remove_subtree(ip, masklen), before - control.len());
+        assert_eq!(mins.remove_subtree(ip, masklen), before - control.len());
+        check(&counts, &mins, &control, ip, masklen - 4);
+        check(&counts, &mins, &control, ip, 8);
+    }
+    assert_eq!(counts.len(), control.len());
+
+    // drop some prefixes in a single pass
+    control.retain(|_, &mut v| v % 5 != 0);
+    counts.retain(|_, _, v| *v % 5 != 0);
+    mins.retain(|_, _, v| *v % 5 != 0);
+    assert_eq!(counts.len(), control.len());
+    for &(ip, masklen) in control.keys().take(200) {
+        check(&counts, &mins, &control, ip, masklen / 2);
+        check(&counts, &mins, &control, ip, masklen);
+    }
+
+    // empty the table
+    let keys: Vec<_> = control.keys().cloned().collect();
+    for (ip, masklen) in keys {
+        let expected = control.remove(&(ip, masklen));
+        assert_eq!(counts.remove(ip, masklen), expected);
+        assert_eq!(mins.remove(ip, masklen), expected);
+        if control.len() % 100 == 0 {
+            check(&counts, &mins, &control, ip, 0);
+            check(&counts, &mins, &control, ip, masklen / 2);
+        }
+    }
+    assert!(counts.is_empty());
+    assert_eq!(counts.aggregate_under(Ipv4Addr::new(0, 0, 0, 0), 0), 0);
+}
+
+#[test]
+fn aggregate_under_panic() {
+    use std::panic::{self, AssertUnwindSafe};
+
+    struct PanicOnDrop(bool);
+
+    impl Drop for PanicOnDrop {
+        fn drop(&mut self) {
+            if self.0 && !std::thread::panicking() {
+                panic!("drop");
+            }
+        }
+    }
+
+    let mut table = AggregateTable::new(Count);
+    table.insert(
+        Ipv6Addr::from_str("2001:db8::").unwrap(),
+        32,
+        PanicOnDrop(false),
+    );
+    for i in 0..64u16 {
+        let ip = Ipv6Addr::new(0x2001, 0xdb8, i << 10, 0, 0, 0, 0, 0);
+        table.insert(ip, 40, PanicOnDrop(i == 7));
+        table.insert(ip, 64, PanicOnDrop(false));
+    }
+    let ip = Ipv6Addr::from_str("2001:db8::").unwrap();
+    let result = panic::catch_unwind(AssertUnwindSafe(|| table.remove_subtree(ip, 33)));
+    assert!(result.is_err());
+    assert_eq!(table.len(), 65);
+    assert_eq!(table.aggregate_under(ip, 32), 65);
+    assert_eq!(table.aggregate_under(ip, 33), 0);
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        table.retain(|_, masklen, _| masklen != 64)
+    }));
+    assert!(result.is_ok());
+    assert_eq!(table.aggregate_under(ip, 32), 33);
+    table.insert(
+        Ipv6Addr::from_str("2001:db8:ff00::").unwrap(),
+        40,
+        PanicOnDrop(false),
+    );
+    assert_eq!(table.aggregate_under(ip, 32), 34);
+    assert_eq!(table.aggregate_under(ip, 0), 34);
+}
+
+#[test]
+#[should_panic(expected = "masklen 33 exceeds the 32 bits of the address")]
+fn aggregate_under_masklen_out_of_range() {
+    let mut table = AggregateTable::new(Count);
+    table.insert(Ipv4Addr::new(10, 0, 0, 0), 32, ());
+    table.aggregate_under(Ipv4Addr::new(10, 0, 0, 0), 33);
+}
+
+#[test]
+fn overlapping_range() {
+    let mut tbl = IpLookupTable::new();
+    let mut control = BTreeMap::new();
+    let mut seed: u32 = 0x5eed_0019;
+    for i in 0..3000u32 {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        let masklen = (seed >> 27) + (seed >> 31);
+        let ip = Ipv4Addr::from(seed & (!0u64 << (32 - masklen)) as u32);
+        tbl.insert(ip, masklen, i);
+        control.insert((ip, masklen), i);
+    }
+
+    for i in 0..500u32 {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        let start = seed;
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        // mostly small ranges, some spanning large parts of the space
+        let end = match i % 4 {
+            0 => seed.max(start),
+            1 => start,
+            _ => start.saturating_add(seed >> (seed % 32)),
+        };
+        let expected: Vec<_> = control
+            .iter()
+            .filter(|&(&(ip, masklen), _)| {
+                let first = u32::from(ip);
+                let last = first | (!0u32).checked_shr(masklen).unwrap_or(0);
+                first <= end && last >= start
+            })
+            .map(|(&(ip, masklen), v)| (ip, masklen, v))
+            .collect();
+        let found: Vec<_> = tbl
+            .overlapping_range(Ipv4Addr::from(start), Ipv4Addr::from(end))
+            .collect();
+        assert_eq!(found, expected);
+    }
+
+    // empty range
+    let start = Ipv4Addr::new(10, 0, 0, 1);
+    let end = Ipv4Addr::new(10, 0, 0, 0);
+    assert_eq!(tbl.overlapping_range(start, end).next(), None);
+}
+
+#[test]
+fn insert_remove_range() {
+    // reference decomposition using integer arithmetic
+    fn decompose(start: u32, end: u32) -> Vec<(Ipv4Addr, u32)> {
+        let (mut start, end) = (u64::from(start), u64::from(end));
+        let mut ret = Vec::new();
+        while start <= end {
+            let mut size = 1u64 << start.trailing_zeros().min(32);
+            while start + size - 1 > end {
+                size >>= 1;
+            }
+            ret.push((Ipv4Addr::from(start as u32), 32 - size.trailing_zeros()));
+            start += size;
+        }
+        ret
+    }
+
+    let mut seed: u32 = 0x0ab0_0020;
+    for i in 0..300u32 {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        let start = seed;
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        let end = match i % 3 {
+            0 => seed.max(start),
+            1 => start,
+            _ => start.saturating_add(seed >> (seed % 32)),
+        };
+        let expected = decompose(start, end);
+
+        let mut tbl = IpLookupTable::new();
+        tbl.insert(Ipv4Addr::from(start), 32, 0);
+        let replaced = tbl.insert_range(Ipv4Addr::from(start), Ipv4Addr::from(end), i);
+        assert_eq!(replaced.len(), usize::from(expected[0].1 == 32));
+        let found: Vec<_> = tbl.iter().map(|(ip, masklen, _)| (ip, masklen)).collect();
+        assert_eq!(found, expected);
+
+        let removed = tbl.remove_range(Ipv4Addr::from(start), Ipv4Addr::from(end));
+        let removed: Vec<_> = removed
+            .into_iter()
+            .map(|(ip, masklen, _)| (ip, masklen))
+            .collect();
+        assert_eq!(removed, expected);
+        assert!(tbl.is_empty());
+    }
+
+    // whole address space and ranges at the edges
+    let mut tbl = IpLookupTable::new();
+    let all = tbl.insert_range(
+        Ipv4Addr::new(0, 0, 0, 0),
+        Ipv4Addr::new(255, 255, 255, 255),
+        1,
+    );
+    assert!(all.is_empty());
+    assert_eq!(tbl.len(), 1);
+    assert_eq!(tbl.exact_match(Ipv4Addr::new(0, 0, 0, 0), 0), Some(&1));
+    let mut tbl = IpLookupTable::new();
+    tbl.insert_range(
+        Ipv6Addr::from_str("ffff::").unwrap(),
+        Ipv6Addr::from_str("ffff:ffff:ffff:ffff:ffff:ffff:ffff:ffff").unwrap(),
+        1,
+    );
+    let found: Vec<_> = tbl.iter().map(|(ip, masklen, _)| (ip, masklen)).collect();
+    assert_eq!(found, vec![(Ipv6Addr::from_str("ffff::").unwrap(), 16)]);
+
+    // empty range
+    let mut tbl = IpLookupTable::new();
+    assert!(tbl
+        .insert_range(Ipv4Addr::new(10, 0, 0, 1), Ipv4Addr::new(10, 0, 0, 0), 1)
+        .is_empty());
+    assert!(tbl.is_empty());
+}
+
+#[test]
+fn prefix() {
+    use std::collections::HashSet;
+
+    for s in &["10.0.0.0/8", "0.0.0.0/0", "10.1.2.3/32", "10.1.2.3/8"] {
+        let prefix: Prefix<Ipv4Addr> = s.parse().unwrap();
+        assert_eq!(prefix.to_string(), *s);
+    }
+    for s in &["2001:db8::/32", "::/0", "2001:db8::1/128"] {
+        let prefix: Prefix<Ipv6Addr> = s.parse().unwrap();
+        assert_eq!(prefix.to_string(), *s);
+    }
+    for s in &[
+        "10.0.0.0",
+        "10.0.0.0/",
+        "10.0.0.0/33",
+        "10.0.0/8",
+        "10.0.0.0/+8",
+        "10.0.0.0/8/8",
+        "/8",
+        "2001:db8::/32",
+    ] {
+        assert!(s.parse::<Prefix<Ipv4Addr>>().is_err(), "{}", s);
+    }
+    assert!("2001:db8::/129".parse::<Prefix<Ipv6Addr>>().is_err());
+
+    let prefix: Prefix<Ipv4Addr> = "10.1.2.3/15".parse().unwrap();
+    assert_eq!(prefix.addr(), Ipv4Addr::new(10, 1, 2, 3));
+    assert_eq!(prefix.network(), Ipv4Addr::new(10, 0, 0, 0));
+    assert_eq!(prefix.len(), 15);
+    assert!(prefix.contains(Ipv4Addr::new(10, 0, 0, 0)));
+    assert!(prefix.contains(Ipv4Addr::new(10, 1, 255, 255)));
+    assert!(!prefix.contains(Ipv4Addr::new(10, 2, 0, 0)));
+    assert!(prefix.contains_prefix("10.1.0.0/16".parse().unwrap()));
+    assert!(!prefix.contains_prefix("10.0.0.0/14".parse().unwrap()));
+    assert_eq!(
+        <(Ipv4Addr, u32)>::from(prefix),
+        (Ipv4Addr::new(10, 1, 2, 3), 15)
+    );
+    assert_eq!(Prefix::from((Ipv4Addr::new(10, 1, 2, 3), 15)), prefix);
+
+    let mut prefixes: Vec<Prefix<Ipv4Addr>> = ["10.0.0.0/16", "10.0.0.0/8", "9.0.0.0/8"]
+        .iter()
+        .map(|s| s.parse().unwrap())
+        .collect();
+    prefixes.sort();
+    let sorted: Vec<_> = prefixes.iter().map(|p| p.to_string()).collect();
+    assert_eq!(sorted, vec!["9.0.0.0/8", "10.0.0.0/8", "10.0.0.0/16"]);
+    let set: HashSet<_> = prefixes.iter().cloned().collect();
+    assert!(set.contains(&"10.0.0.0/8".parse().unwrap()));
+
+    // host bits are ignored by comparison and hashing
+    let host: Prefix<Ipv4Addr> = "10.1.2.3/8".parse().unwrap();
+    assert_eq!(host, "10.0.0.0/8".parse().unwrap());
+    assert_ne!(host, "10.0.0.0/9".parse().unwrap());
+    assert!(set.contains(&host));
+    assert!(host < "10.0.0.0/16".parse().unwrap());
+    assert!(host > "9.255.255.255/32".parse().unwrap());
+
+    // table api
+    let mut tbl = IpLookupTable::new();
+    for (i, prefix) in prefixes.iter().enumerate() {
+        assert_eq!(tbl.insert_prefix(*prefix, i), None);
+    }
+    assert_eq!(
+        tbl.insert_prefix("10.0.255.255/16".parse().unwrap(), 5),
+        Some(2)
+    );
+    assert_eq!(tbl.get("10.0.0.0/16".parse().unwrap()), Some(&5));
+    assert_eq!(tbl["10.0.0.0/8".parse::<Prefix<_>>().unwrap()], 1);
+    *tbl.get_mut(prefixes[0]).unwrap() = 7;
+    assert_eq!(
+        tbl.lookup(Ipv4Addr::new(10, 0, 1, 1)),
+        Some((prefixes[2], &5))
+    );
+    assert_eq!(tbl.lookup(Ipv4Addr::new(11, 0, 1, 1)), None);
+    let found: Vec<_> = tbl.iter().prefixes().collect();
+    assert_eq!(
+        found,
+        vec![(prefixes[0], &7), (prefixes[1], &1), (prefixes[2], &5)]
+    );
+    let found: Vec<_> = tbl.matches(Ipv4Addr::new(10, 0, 0, 1)).prefixes().collect();
+    assert_eq!(found, vec![(prefixes[1], &1), (prefixes[2], &5)]);
+    assert_eq!(tbl.remove_prefix(prefixes[1]), Some(1));
+    assert_eq!(tbl.get(prefixes[1]), None);
+}
+
+#[test]
+fn try_insert_remove_exact_match() {
+    let mut tbl = IpLookupTable::new();
+    let ip = Ipv4Addr::new(10, 0, 0, 0);
+    assert_eq!(tbl.try_insert(ip, 8, 1), Ok(None));
+    assert_eq!(tbl.try_insert(ip, 8, 2), Ok(Some(1)));
+    assert_eq!(
+        tbl.try_insert(ip, 33, 3),
+        Err(TableError::MasklenOutOfRange)
+    );
+    assert_eq!(
+        tbl.try_insert(Ipv4Addr::new(10, 128, 0, 0), 8, 3),
+        Err(TableError::HostBitsSet)
+    );
+    assert_eq!(
+        tbl.try_insert(Ipv4Addr::new(10, 0, 0, 1), 31, 3),
+        Err(TableError::HostBitsSet)
+    );
+    assert_eq!(tbl.len(), 1);
+
+    assert_eq!(tbl.try_exact_match(ip, 8), Ok(Some(&2)));
+    assert_eq!(tbl.try_exact_match(ip, 9), Ok(None));
+    assert_eq!(tbl.try_exact_match(ip, 6), Err(TableError::HostBitsSet));
+    assert_eq!(
+        tbl.try_exact_match(ip, 64),
+        Err(TableError::MasklenOutOfRange)
+    );
+
+    assert_eq!(tbl.try_remove(ip, 33), Err(TableError::MasklenOutOfRange));
+    assert_eq!(tbl.try_remove(ip, 4), Err(TableError::HostBitsSet));
+    assert_eq!(tbl.try_remove(ip, 8), Ok(Some(2)));
+    assert_eq!(tbl.try_remove(ip, 8), Ok(None));
+    assert!(tbl.is_empty());
+
+    let mut tbl = IpLookupTable::new();
+    let ip = Ipv6Addr::from_str("2001:db8::").unwrap();
+    assert_eq!(tbl.try_insert(ip, 128, ()), Ok(None));
+    assert_eq!(
+        tbl.try_insert(ip, 129, ()),
+        Err(TableError::MasklenOutOfRange)
+    );
+    assert_eq!(
+        TableError::HostBitsSet.to_string(),
+        "prefix has bits set to the right of mask"
+    );
+}
+
+#[test]
+#[should_panic(expected = "prefix has bits set to the right of mask")]
+fn insert_host_bits_set() {
+    let mut tbl = IpLookupTable::new();
+    tbl.insert(Ipv4Addr::new(10, 0, 0, 1), 24, ());
+}
+
+#[test]
+#[should_panic(expected = "prefix has bits set to the right of mask")]
+fn remove_host_bits_set() {
+    let mut tbl: IpLookupTable<Ipv4Addr, ()> = IpLookupTable::new();
+    tbl.remove(Ipv4Addr::new(10, 0, 0, 1), 24);
+}
+
+#[test]
+#[should_panic(expected = "prefix has bits set to the right of mask")]
+fn entry_host_bits_set() {
+    let mut tbl: IpLookupTable<Ipv6Addr, u32> = IpLookupTable::new();
+    tbl.entry(Ipv6Addr::from_str("2001:db8::1").unwrap(), 32)
+        .or_insert(0);
+}
+
+#[test]
+#[should_panic(expected = "prefix has bits set to the right of mask")]
+fn exact_match_host_bits_set() {
+    let mut tbl = IpLookupTable::new();
+    tbl.insert(Ipv4Addr::new(10, 0, 0, 0), 8, ());
+    tbl.exact_match(Ipv4Addr::new(10, 1, 0, 0), 8);
+}
+
+#[test]
+#[should_panic(expected = "prefix has bits set to the right of mask")]
+fn exact_match_mut_host_bits_set() {
+    let mut tbl = IpLookupTable::new();
+    tbl.insert(Ipv4Addr::new(10, 0, 0, 0), 8, ());
+    tbl.exact_match_mut(Ipv4Addr::new(10, 0, 0, 128), 8);
+}
+
+#[test]
+#[should_panic]
+fn insert_masklen_out_of_range() {
+    let mut tbl = IpLookupTable::new();
+    tbl.insert(Ipv4Addr::new(10, 0, 0, 0), 33, ());
+}
+
+#[test]
+#[should_panic]
+fn exact_match_masklen_out_of_range() {
+    let tbl: IpLookupTable<Ipv6Addr, ()> = IpLookupTable::new();
+    tbl.exact_match(Ipv6Addr::from_str("2001:db8::").unwrap(), 129);
+}
+
+#[test]
+fn insert_normalized() {
+    let mut tbl = IpLookupTable::new();
+    let mut control = BTreeMap::new();
+    let mut seed: u32 = 0xdead_beef;
+    for i in 0..10_000 {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        let masklen = (seed >> 27) + (seed >> 31);
+        let ip = Ipv4Addr::from(seed.rotate_left(7));
+        let network = Prefix::new(ip, masklen).network();
+        let (ret, normalized) = tbl.insert_normalized(ip, masklen, i);
+        assert_eq!(normalized, network != ip);
+        assert_eq!(ret, control.insert((network, masklen), i));
+    }
+    assert_eq!(tbl.len(), control.len());
+    let mut entries: Vec<_> = tbl
+        .iter()
+        .map(|(ip, masklen, &v)| ((ip, masklen), v))
+        .collect();
+    entries.sort();
+    assert_eq!(entries, control.into_iter().collect::<Vec<_>>());
+
+    let mut tbl = IpLookupTable::new();
+    let ip = Ipv6Addr::from_str("2001:db8::1").unwrap();
+    assert_eq!(tbl.insert_normalized(ip, 128, ()), (None, false));
+    assert_eq!(tbl.insert_normalized(ip, 0, ()), (None, true));
+    assert_eq!(tbl.exact_match(Ipv6Addr::from(0), 0), Some(&()));
+}
+
+#[test]
+#[should_panic(expected = "masklen 33 exceeds the 32 bits of the address")]
+fn insert_normalized_masklen_out_of_range() {
+    let mut tbl = IpLookupTable::new();
+    tbl.insert_normalized(Ipv4Addr::new(10, 0, 0, 0), 33, ());
+}
+
+#[test]
+#[should_panic(expected = "masklen 129 exceeds the 128 bits of the address")]
+fn insert_normalized_masklen_out_of_range_ipv6() {
+    let mut tbl = IpLookupTable::new();
+    tbl.insert_normalized(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1), 129, ());
+}
+
+#[test]
+fn dual_stack() {
+    let mut tbl = DualStackTable::new();
+    let mut control = BTreeMap::new();
+    let mut seed: u32 = 0x1234_5678;
+    for i in 0..10_000u32 {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        let masklen = (seed >> 27) + (seed >> 31);
+        let ip = if i % 2 == 0 {
+            IpAddr::V4(Prefix::new(Ipv4Addr::from(seed), masklen).network())
+        } else {
+            let ip = Ipv6Addr::from(u128::from(seed) << 96);
+            IpAddr::V6(Prefix::new(ip, masklen).network())
+        };
+        if seed & 0x100 == 0 {
+            assert_eq!(tbl.remove(ip, masklen), control.remove(&(ip, masklen)));
+        } else {
+            assert_eq!(tbl.insert(ip, masklen, i), control.insert((ip, masklen), i));
+        }
+    }
+    assert_eq!(tbl.len(), control.len());
+    assert_eq!(tbl.ipv4().len() + tbl.ipv6().len(), control.len());
+
+    // IPv4 prefixes sort before IPv6 prefixes in the control too
+    let mut entries: Vec<_> = tbl
+        .iter()
+        .map(|(ip, masklen, &v)| ((ip, masklen), v))
+        .collect();
+    assert!(entries[..tbl.ipv4().len()].iter().all(|e| e.0 .0.is_ipv4()));
+    entries.sort();
+    assert_eq!(
+        entries,
+        control.iter().map(|(&k, &v)| (k, v)).collect::<Vec<_>>()
+    );
+    assert_eq!(tbl.iter().rev().count(), control.len());
+
+    for &(ip, masklen) in control.keys() {
+        assert_eq!(tbl.exact_match(ip, masklen), control.get(&(ip, masklen)));
+    }
+    let lookups = [
+        IpAddr::V4(Ipv4Addr::from(seed)),
+        IpAddr::V6(Ipv6Addr::from(u128::from(seed) << 96)),
+    ];
+    for &ip in &lookups {
+        let expected = control
+            .iter()
+            .filter(|&(&(prefix, masklen), _)| match (prefix, ip) {
+                (IpAddr::V4(p), IpAddr::V4(a)) => Prefix::new(p, masklen).contains(a),
+                (IpAddr::V6(p), IpAddr::V6(a)) => Prefix::new(p, masklen).contains(a),
+                _ => false,
+            })
+            .max_by_key(|&(&(_, masklen), _)| masklen)
+            .map(|(&(prefix, masklen), value)| (prefix, masklen, value));
+        assert_eq!(tbl.longest_match(ip), expected);
+    }
+
+    for (_, _, value) in tbl.iter_mut() {
+        *value += 1;
+    }
+    let (ip, masklen) = *control.keys().next().unwrap();
+    assert_eq!(
+        tbl.exact_match(ip, masklen),
+        Some(&(control[&(ip, masklen)] + 1))
+    );
+}
+
+#[test]
+fn dual_stack_eq_hash() {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    fn hash_of<T: Hash>(t: &T) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        t.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    let v4: IpAddr = Ipv4Addr::new(10, 0, 0, 0).into();
+    let v6: IpAddr = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0).into();
+    let mut a = DualStackTable::new();
+    a.insert(v4, 8, 1);
+    a.insert(v6, 32, 2);
+    let mut b = DualStackTable::new();
+    b.insert(v6, 32, 2);
+    b.insert(v4, 8, 1);
+    assert_eq!(a, b);
+    assert_eq!(hash_of(&a), hash_of(&b));
+
+    // push_down by a longer prefix is not undone by removing it
+    b.insert(v4, 12, 3);
+    b.remove(v4, 12);
+    assert_eq!(a, b);
+    assert_eq!(hash_of(&a), hash_of(&b));
+
+    b.insert(v6, 32, 3);
+    assert_ne!(a, b);
+    assert_ne!(hash_of(&a), hash_of(&b));
+}
+
+#[test]
+fn dual_stack_try() {
+    let mut tbl = DualStackTable::new();
+    let v4 = IpAddr::from_str("10.0.0.0").unwrap();
+    let v6 = IpAddr::from_str("2001:db8::").unwrap();
+    tbl.insert(v4, 8, 4);
+    tbl.insert(v6, 32, 6);
+
+    assert_eq!(tbl.try_exact_match(v4, 8), Ok(Some(&4)));
+    assert_eq!(tbl.try_exact_match(v6, 48), Ok(None));
+    assert_eq!(
+        tbl.try_exact_match(v4, 33),
+        Err(TableError::MasklenOutOfRange)
+    );
+    assert_eq!(tbl.try_exact_match(v6, 16), Err(TableError::HostBitsSet));
+
+    assert_eq!(tbl.try_remove(v4, 4), Err(TableError::HostBitsSet));
+    assert_eq!(tbl.try_remove(v6, 129), Err(TableError::MasklenOutOfRange));
+    assert_eq!(tbl.len(), 2);
+    assert_eq!(tbl.try_remove(v4, 8), Ok(Some(4)));
+    assert_eq!(tbl.try_remove(v6, 32), Ok(Some(6)));
+    assert_eq!(tbl.try_remove(v6, 32), Ok(None));
+    assert!(tbl.is_empty());
+}
+
+#[test]
+#[should_panic(expected = "prefix has bits set to the right of mask")]
+fn dual_stack_remove_host_bits_set() {
+    let mut tbl: DualStackTable<()> = DualStackTable::new();
+    tbl.remove(IpAddr::from_str("10.0.0.1").unwrap(), 24);
+}
+
+#[test]
+#[should_panic(expected = "prefix has bits set to the right of mask")]
+fn dual_stack_exact_match_host_bits_set() {
+    let tbl: DualStackTable<()> = DualStackTable::new();
+    tbl.exact_match(IpAddr::from_str("2001:db8::1").unwrap(), 64);
+}
+
+#[test]
+fn longest_match_translated() {
+    let mut tbl = DualStackTable::new();
+    let v4 = IpAddr::from_str("192.0.2.0").unwrap();
+    let v6 = IpAddr::from_str("::").unwrap();
+    tbl.insert(v4, 24, "v4");
+    tbl.insert(v6, 0, "v6");
+
+    let cases = [
+        (
+            "::ffff:192.0.2.1",
+            Translation {
+                ipv4_mapped: true,
+                ..Translation::NONE
+            },
+        ),
+        (
+            "64:ff9b::192.0.2.1",
+            Translation {
+                nat64: true,
+                ..Translation::NONE
+            },
+        ),
+        (
+            "2002:c000:2ff:1::1",
+            Translation {
+                six_to_four: true,
+                ..Translation::NONE
+            },
+        ),
+    ];
+    for &(ip, translation) in &cases {
+        let ip = IpAddr::from_str(ip).unwrap();
+        assert_eq!(
+            tbl.longest_match_translated(ip, translation),
+            Some((v4, 24, &"v4"))
+        );
+        assert_eq!(
+            tbl.longest_match_translated(ip, Translation::ALL),
+            Some((v4, 24, &"v4"))
+        );
+        // other kinds of translation do not apply
+        let other = Translation {
+            ipv4_mapped: !translation.ipv4_mapped,
+            nat64: !translation.nat64,
+            six_to_four: !translation.six_to_four,
+        };
+        assert_eq!(
+            tbl.longest_match_translated(ip, other),
+            Some((v6, 0, &"v6"))
+        );
+        assert_eq!(tbl.longest_match(ip), Some((v6, 0, &"v6")));
+    }
+
+    // embedded address not in the IPv4 table falls back to the IPv6 table
+    let ip = IpAddr::from_str("::ffff:198.51.100.1").unwrap();
+    assert_eq!(
+        tbl.longest_match_translated(ip, Translation::ALL),
+        Some((v6, 0, &"v6"))
+    );
+    // near misses of the translated prefixes
+    for ip in &[
+        "::fffe:192.0.2.1",
+        "::192.0.2.1",
+        "64:ff9b:1::192.0.2.1",
+        "2003:c000:201::",
+    ] {
+        let ip = Ipv6Addr::from_str(ip).unwrap();
+        assert_eq!(Translation::ALL.embedded_ipv4(ip), None);
+    }
+    // IPv4 lookups are unaffected
+    let ip = IpAddr::from_str("192.0.2.255").unwrap();
+    assert_eq!(
+        tbl.longest_match_translated(ip, Translation::NONE),
+        Some((v4, 24, &"v4"))
+    );
+    assert_eq!(Translation::default(), Translation::NONE);
+}